@@ -0,0 +1,140 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::download;
+use crate::jdk::{JdkAsset, JdkPackage, JdkVendor, JdkVersion, Libc, Os, Platform};
+
+const API_ENDPOINT: &str = "https://api.adoptium.net/v3/";
+
+// the parts of /v3/assets/feature_releases we use
+#[derive(Debug, Deserialize)]
+struct FeatureRelease {
+    release_name: String,
+    binaries: Vec<Binary>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Binary {
+    package: Package,
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    name: String,
+    link: String,
+    checksum: Option<String>,
+}
+
+fn assets_url(version: JdkVersion, package: JdkPackage, platform: Platform) -> String {
+    let os = match (platform.os, platform.libc) {
+        (Os::Linux, Libc::Musl) => "alpine-linux",
+        (os, _) => os.name(),
+    };
+    format!(
+        "{API_ENDPOINT}assets/feature_releases/{version}/ga?architecture={}&os={os}\
+         &image_type={}&jvm_impl=hotspot&heap_size=normal&vendor=eclipse\
+         &page_size=1&sort_order=DESC",
+        platform.arch.name(),
+        package.name(),
+    )
+}
+
+// the newest release in the response and its archive. Only the package is
+// looked at, the installer (an msi on Windows) is no use to us
+fn parse_assets(body: &str, version: JdkVersion) -> Result<(String, JdkAsset)> {
+    let releases: Vec<FeatureRelease> =
+        serde_json::from_str(body).context("unexpected Adoptium API response")?;
+    let release = releases
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Adoptium has no JDK {version} build for this platform"))?;
+    let binary = release
+        .binaries
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("{} has no binaries", release.release_name))?;
+    let name = JdkVendor::Temurin
+        .release_from_tag(version, &release.release_name)
+        .ok_or_else(|| anyhow!("unrecognized Temurin release {}", release.release_name))?;
+    let Package {
+        name: filename,
+        link,
+        checksum,
+    } = binary.package;
+    let asset = JdkAsset {
+        url: link,
+        filename,
+        checksum_url: None,
+        checksum: checksum.map(|checksum| checksum.to_ascii_lowercase()),
+    };
+    Ok((name, asset))
+}
+
+// latest Temurin release for the platform and the exact archive to download
+pub fn latest_asset(
+    version: JdkVersion,
+    package: JdkPackage,
+    platform: Platform,
+) -> Result<(String, JdkAsset)> {
+    let body = download::fetch_text(&assets_url(version, package, platform))?;
+    parse_assets(&body, version)
+}
+
+#[test]
+fn test_assets_url() {
+    let platform = Platform::from_volt("macos", "aarch64").unwrap();
+    assert_eq!(
+        assets_url(JdkVersion::default(), JdkPackage::Jdk, platform),
+        "https://api.adoptium.net/v3/assets/feature_releases/21/ga?architecture=aarch64&os=mac\
+         &image_type=jdk&jvm_impl=hotspot&heap_size=normal&vendor=eclipse&page_size=1&sort_order=DESC"
+    );
+    let alpine = Platform {
+        libc: Libc::Musl,
+        ..Platform::from_volt("linux", "x86_64").unwrap()
+    };
+    assert!(
+        assets_url(JdkVersion::default(), JdkPackage::Jdk, alpine).contains("&os=alpine-linux&")
+    );
+    assert!(assets_url(JdkVersion::default(), JdkPackage::Jre, alpine).contains("&image_type=jre&"));
+}
+
+#[test]
+fn test_parse_assets() {
+    let body = r#"[{
+        "binaries": [{
+            "architecture": "x64",
+            "image_type": "jdk",
+            "installer": {
+                "link": "https://github.com/adoptium/temurin21-binaries/releases/download/jdk-21.0.2%2B13/OpenJDK21U-jdk_x64_windows_hotspot_21.0.2_13.msi",
+                "name": "OpenJDK21U-jdk_x64_windows_hotspot_21.0.2_13.msi"
+            },
+            "os": "windows",
+            "package": {
+                "checksum": "B6C17E747AE78CDD6DE4D7532B3164B277DAEE97C007D3EAA15B3C8ED4DB5B53",
+                "link": "https://github.com/adoptium/temurin21-binaries/releases/download/jdk-21.0.2%2B13/OpenJDK21U-jdk_x64_windows_hotspot_21.0.2_13.zip",
+                "name": "OpenJDK21U-jdk_x64_windows_hotspot_21.0.2_13.zip",
+                "size": 206454529
+            }
+        }],
+        "release_name": "jdk-21.0.2+13",
+        "vendor": "eclipse",
+        "version_data": { "semver": "21.0.2+13.0.LTS" }
+    }]"#;
+    let (release, asset) = parse_assets(body, JdkVersion::default()).unwrap();
+    assert_eq!(release, "21.0.2+13");
+    assert_eq!(
+        asset.url,
+        "https://github.com/adoptium/temurin21-binaries/releases/download/jdk-21.0.2%2B13/OpenJDK21U-jdk_x64_windows_hotspot_21.0.2_13.zip"
+    );
+    assert_eq!(
+        asset.filename,
+        "OpenJDK21U-jdk_x64_windows_hotspot_21.0.2_13.zip"
+    );
+    assert_eq!(
+        asset.checksum.as_deref(),
+        Some("b6c17e747ae78cdd6de4d7532b3164b277daee97c007d3eaa15b3c8ed4db5b53")
+    );
+
+    assert!(parse_assets("[]", JdkVersion::default()).is_err());
+    assert!(parse_assets(r#"{"errorMessage": "bad request"}"#, JdkVersion::default()).is_err());
+}
@@ -0,0 +1,618 @@
+use anyhow::{anyhow, Context, Result};
+use regex::Regex::new as Regexp;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use crate::options::section;
+use crate::runner::CommandRunner;
+#[cfg(test)]
+use crate::runner::MockRunner;
+use crate::version::Version;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildTool {
+    Sbt,
+    Mill,
+    Gradle,
+    Maven,
+    ScalaCli,
+}
+
+impl BuildTool {
+    pub fn name(self) -> &'static str {
+        match self {
+            BuildTool::Sbt => "sbt",
+            BuildTool::Mill => "mill",
+            BuildTool::Gradle => "gradle",
+            BuildTool::Maven => "maven",
+            BuildTool::ScalaCli => "scala-cli",
+        }
+    }
+
+    // the name Metals knows the tool's build server by
+    fn bsp_name(self) -> Option<&'static str> {
+        match self {
+            BuildTool::Mill => Some("mill-bsp"),
+            BuildTool::ScalaCli => Some("scala-cli"),
+            BuildTool::Gradle => Some("gradle"),
+            BuildTool::Sbt | BuildTool::Maven => None,
+        }
+    }
+
+    fn markers(self) -> &'static [&'static str] {
+        match self {
+            BuildTool::Sbt => &["build.sbt", "project/build.properties"],
+            BuildTool::Mill => &["build.sc", "build.mill"],
+            BuildTool::Gradle => &["build.gradle", "build.gradle.kts"],
+            BuildTool::Maven => &["pom.xml"],
+            BuildTool::ScalaCli => &["project.scala"],
+        }
+    }
+
+    fn present(self, root: &Path) -> bool {
+        match self {
+            BuildTool::Gradle => self
+                .markers()
+                .iter()
+                .filter_map(|marker| std::fs::read_to_string(root.join(marker)).ok())
+                .any(|build| applies_scala_plugin(&build)),
+            BuildTool::ScalaCli => {
+                root.join("project.scala").exists() || has_scala_cli_sources(root)
+            }
+            _ => self
+                .markers()
+                .iter()
+                .any(|marker| root.join(marker).exists()),
+        }
+    }
+}
+
+// only Gradle builds of Scala code are of interest, in either the Groovy
+// (`id 'scala'`, `apply plugin: 'scala'`) or the Kotlin DSL (`scala`,
+// `id("scala")` in the plugins block)
+fn applies_scala_plugin(build: &str) -> bool {
+    let Ok(re) = Regexp(
+        r#"(?m)^\s*(?:id\s*\(?\s*["']scala["']\s*\)?|apply\s*\(?\s*plugin\s*[:=]\s*["']scala["']\s*\)?|`?scala`?)\s*$"#,
+    ) else {
+        return false;
+    };
+    re.is_match(build)
+}
+
+#[test]
+fn test_applies_scala_plugin() {
+    assert!(applies_scala_plugin("plugins {\n    id 'scala'\n}\n"));
+    assert!(applies_scala_plugin("plugins {\n    id(\"scala\")\n}\n"));
+    assert!(applies_scala_plugin(
+        "plugins {\n    scala\n    application\n}\n"
+    ));
+    assert!(applies_scala_plugin("apply plugin: 'scala'\n"));
+    assert!(!applies_scala_plugin("plugins {\n    id 'java'\n}\n"));
+    assert!(!applies_scala_plugin(
+        "dependencies {\n    implementation 'org.scala-lang:scala-library:2.13.12'\n}\n"
+    ));
+}
+
+// scala-cli doesn't need a project.scala, `//> using` directives or a
+// scala-cli shebang in any source next to it make a project too. Plain .sc
+// files don't count, they may just as well be worksheets
+fn has_scala_cli_sources(root: &Path) -> bool {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return false;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "scala" || ext == "sc")
+        })
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .any(|contents| is_scala_cli_source(&contents))
+}
+
+fn is_scala_cli_source(contents: &str) -> bool {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .take_while(|line| line.starts_with("//") || line.starts_with("#!"))
+        .any(|line| {
+            line.starts_with("//> using") || line.starts_with("#!") && line.contains("scala-cli")
+        })
+}
+
+#[test]
+fn test_is_scala_cli_source() {
+    assert!(is_scala_cli_source(
+        "//> using scala 3.3.1\n//> using dep com.lihaoyi::os-lib:0.9.3\n\nobject Main"
+    ));
+    assert!(is_scala_cli_source(
+        "#!/usr/bin/env -S scala-cli shebang\nprintln(1)\n"
+    ));
+    assert!(is_scala_cli_source(
+        "// a comment first\n//> using toolkit latest\n"
+    ));
+    // directives only count in the header
+    assert!(!is_scala_cli_source("object Main\n//> using scala 3.3.1\n"));
+    assert!(!is_scala_cli_source("#!/usr/bin/env amm\nprintln(1)\n"));
+    assert!(!is_scala_cli_source("val x = 1\n"));
+}
+
+// build tools whose build definition sits in the workspace root
+pub fn detect(root: &Path) -> Vec<BuildTool> {
+    [
+        BuildTool::Sbt,
+        BuildTool::Mill,
+        BuildTool::Gradle,
+        BuildTool::Maven,
+        BuildTool::ScalaCli,
+    ]
+    .into_iter()
+    .filter(|tool| tool.present(root))
+    .collect()
+}
+
+// the buildServer option, for workspaces with more than one build
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuildServer {
+    #[default]
+    Auto,
+    Sbt,
+    Bloop,
+    Mill,
+    Gradle,
+    ScalaCli,
+}
+
+impl BuildServer {
+    pub const NAMES: &'static str = "sbt, bloop, mill, gradle, scala-cli or auto";
+
+    // what Metals calls it in defaultBspToImport, None leaves it to detection
+    pub fn bsp_name(self) -> Option<&'static str> {
+        match self {
+            BuildServer::Auto => None,
+            BuildServer::Sbt => Some("sbt"),
+            BuildServer::Bloop => Some("bloop"),
+            BuildServer::Mill => BuildTool::Mill.bsp_name(),
+            BuildServer::Gradle => BuildTool::Gradle.bsp_name(),
+            BuildServer::ScalaCli => BuildTool::ScalaCli.bsp_name(),
+        }
+    }
+
+    // Err is the value the user set when it isn't one we know
+    pub fn from_options(options: Option<&Value>) -> Result<BuildServer, String> {
+        match options.and_then(|options| options.get("buildServer")) {
+            None | Some(Value::Null) => Ok(BuildServer::Auto),
+            Some(Value::String(name)) if name.trim().is_empty() => Ok(BuildServer::Auto),
+            Some(Value::String(name)) => {
+                serde_json::from_value(Value::String(name.trim().to_ascii_lowercase()))
+                    .map_err(|_| name.clone())
+            }
+            Some(value) => Err(value.to_string()),
+        }
+    }
+}
+
+#[test]
+fn test_build_server() {
+    let build_server = |value: Value| {
+        BuildServer::from_options(Some(&serde_json::json!({ "buildServer": value })))
+    };
+    assert_eq!(build_server("bloop".into()), Ok(BuildServer::Bloop));
+    assert_eq!(build_server("Scala-CLI".into()), Ok(BuildServer::ScalaCli));
+    assert_eq!(build_server("".into()), Ok(BuildServer::Auto));
+    assert_eq!(build_server("auto".into()), Ok(BuildServer::Auto));
+    assert_eq!(build_server("maven".into()), Err("maven".to_string()));
+    assert_eq!(build_server(3.into()), Err("3".to_string()));
+    assert_eq!(BuildServer::from_options(None), Ok(BuildServer::Auto));
+
+    assert_eq!(BuildServer::Mill.bsp_name(), Some("mill-bsp"));
+    assert_eq!(BuildServer::Auto.bsp_name(), None);
+}
+
+// a build server Metals can import without asking, for builds where the
+// choice is obvious. sbt projects are left to Metals, which asks whether to
+// use Bloop or sbt's own server
+pub fn default_bsp(tools: &[BuildTool]) -> Option<&'static str> {
+    match tools {
+        [tool] => tool.bsp_name(),
+        _ => None,
+    }
+}
+
+// the connection file sbt, Mill, scala-cli and others leave in `.bsp/`,
+// as the BSP spec describes it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BspConnection {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub bsp_version: String,
+    #[serde(default)]
+    pub languages: Vec<String>,
+    // how the editor starts the server
+    #[serde(default)]
+    pub argv: Vec<String>,
+}
+
+impl BspConnection {
+    // servers that don't say are assumed to, Metals will find out
+    fn speaks_scala(&self) -> bool {
+        self.languages.is_empty() || self.languages.iter().any(|language| language == "scala")
+    }
+}
+
+// every readable connection file in `root`, sorted by name
+pub fn bsp_connections(root: &Path) -> Vec<BspConnection> {
+    let mut connections: Vec<BspConnection> = std::fs::read_dir(root.join(".bsp"))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            let contents = std::fs::read_to_string(path).ok()?;
+            serde_json::from_str(&contents).ok()
+        })
+        .collect();
+    connections.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    connections
+}
+
+// the server to import from the connection files there are, when that's
+// clear: the only Scala one, or the one of the build tool found. sbt
+// writes its file every time it starts, whether or not it's meant to be
+// the build server, so it's left to Metals like default_bsp does
+pub fn preferred_bsp(connections: &[BspConnection], tools: &[BuildTool]) -> Option<String> {
+    let candidates: Vec<_> = connections
+        .iter()
+        .filter(|connection| connection.speaks_scala() && connection.name != "sbt")
+        .collect();
+    match candidates[..] {
+        [connection] => Some(connection.name.clone()),
+        _ => candidates
+            .iter()
+            .find(|connection| {
+                tools
+                    .iter()
+                    .any(|tool| tool.bsp_name() == Some(connection.name.as_str()))
+            })
+            .map(|connection| connection.name.clone()),
+    }
+}
+
+#[test]
+fn test_bsp_connections() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/bsp");
+    let connections = bsp_connections(&fixture);
+    assert_eq!(connections.len(), 1);
+    let sbt = &connections[0];
+    assert_eq!(
+        (
+            sbt.name.as_str(),
+            sbt.version.as_str(),
+            sbt.bsp_version.as_str()
+        ),
+        ("sbt", "1.9.9", "2.1.0-M1")
+    );
+    assert_eq!(sbt.languages, ["scala"]);
+    assert_eq!(sbt.argv.last().map(String::as_str), Some("-bsp"));
+    assert_eq!(preferred_bsp(&connections, &detect(&fixture)), None);
+
+    let connection = |name: &str, languages: &[&str]| BspConnection {
+        name: name.to_string(),
+        version: String::new(),
+        bsp_version: String::new(),
+        languages: languages
+            .iter()
+            .map(|language| language.to_string())
+            .collect(),
+        argv: vec![],
+    };
+    let mill = connection("mill-bsp", &["scala", "java"]);
+    let scala_cli = connection("scala-cli", &[]);
+    let kotlin = connection("kotlin-bsp", &["kotlin"]);
+    assert_eq!(
+        preferred_bsp(&[sbt.clone(), mill.clone(), kotlin], &[]).as_deref(),
+        Some("mill-bsp")
+    );
+    let both = [mill, scala_cli];
+    assert_eq!(preferred_bsp(&both, &[]), None);
+    assert_eq!(
+        preferred_bsp(&both, &[BuildTool::ScalaCli]).as_deref(),
+        Some("scala-cli")
+    );
+}
+
+// the build servers Metals can connect to in `root`: Bloop once Metals
+// has exported the build to it, and every server with a connection file,
+// sorted by name
+pub fn bsp_servers(root: &Path) -> Vec<String> {
+    let mut servers: Vec<String> = bsp_connections(root)
+        .into_iter()
+        .map(|connection| connection.name)
+        .collect();
+    if root.join(".bloop").is_dir() {
+        servers.push("Bloop".to_string());
+    }
+    servers.sort_unstable_by_key(|name| name.to_lowercase());
+    servers.dedup();
+    servers
+}
+
+#[test]
+fn test_bsp_servers() {
+    let root = std::env::temp_dir().join("scala-lapce-test-bsp-servers");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join(".bsp")).unwrap();
+    assert!(bsp_servers(&root).is_empty());
+
+    std::fs::write(
+        root.join(".bsp/sbt.json"),
+        r#"{"name":"sbt","version":"1.9.9","bspVersion":"2.1.0-M1","languages":["scala"],"argv":["java","-Xms100m","-jar","sbt-launch.jar","-bsp"]}"#,
+    )
+    .unwrap();
+    std::fs::write(root.join(".bsp/scala-cli.json"), r#"{"name":"scala-cli"}"#).unwrap();
+    std::fs::write(root.join(".bsp/notes.txt"), "").unwrap();
+    std::fs::write(root.join(".bsp/broken.json"), "{").unwrap();
+    std::fs::create_dir_all(root.join(".bloop")).unwrap();
+    assert_eq!(bsp_servers(&root), ["Bloop", "sbt", "scala-cli"]);
+}
+
+// the Mill version the project pins, in `.mill-version` or, since Mill
+// 0.11, `.config/mill-version`
+pub fn mill_version(root: &Path) -> Option<String> {
+    [".mill-version", ".config/mill-version"]
+        .iter()
+        .filter_map(|file| std::fs::read_to_string(root.join(file)).ok())
+        .find_map(|contents| {
+            let version = contents.lines().next()?.trim();
+            (!version.is_empty()).then(|| version.to_string())
+        })
+}
+
+// version of scala-cli on PATH, from `scala-cli version`:
+// `Scala CLI version: 1.1.2` followed by the default Scala version
+pub fn scala_cli_version(runner: &dyn CommandRunner) -> Option<String> {
+    let output = runner.run("scala-cli", &["version"]).ok()?;
+    if !output.success {
+        return None;
+    }
+    let re = Regexp(r"Scala CLI version:? (\S+)").ok()?;
+    re.captures(&output.stdout)
+        .map(|captures| captures[1].to_string())
+}
+
+// Bloop's version from `bloop about`, which starts with `bloop v1.5.11`,
+// or the shorter `bloop --version`
+fn parse_bloop_version(output: &str) -> Option<String> {
+    let re = Regexp(r"(?m)^bloop v?(\d+\.\d+\.\d+\S*)").ok()?;
+    re.captures(output).map(|captures| captures[1].to_string())
+}
+
+// `--version` only needs the client, `about` asks the Bloop server and
+// may have to start it, so it's only worth it for a build Bloop knows
+pub fn bloop_version(root: Option<&Path>, runner: &dyn CommandRunner) -> Option<String> {
+    let run = |args: &[&str]| {
+        let output = runner.run("bloop", args).ok()?;
+        output
+            .success
+            .then(|| parse_bloop_version(&output.stdout))
+            .flatten()
+    };
+    run(&["--version"]).or_else(|| {
+        root.filter(|root| root.join(".bloop").is_dir())
+            .and_then(|_| run(&["about"]))
+    })
+}
+
+#[test]
+fn test_bloop_version() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/bloop");
+    let about = std::fs::read_to_string(fixture.join("bloop-about.txt")).unwrap();
+    assert_eq!(parse_bloop_version(&about).as_deref(), Some("1.5.11"));
+    assert_eq!(
+        parse_bloop_version("bloop v2.0.0-RC1\n").as_deref(),
+        Some("2.0.0-RC1")
+    );
+    assert_eq!(parse_bloop_version("command not found: bloop"), None);
+
+    let runner = MockRunner::default().with("bloop about", true, &about, "");
+    assert_eq!(
+        bloop_version(Some(&fixture), &runner).as_deref(),
+        Some("1.5.11")
+    );
+    // without an exported build there's no asking the server
+    let sbt = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sbt-single");
+    assert_eq!(bloop_version(Some(&sbt), &runner), None);
+    let runner = MockRunner::default().with("bloop --version", true, "bloop v1.5.11\n", "");
+    assert_eq!(bloop_version(None, &runner).as_deref(), Some("1.5.11"));
+}
+
+// Metals' own Bloop settings, passed on to it as they are. Unset leaves
+// the choice to Metals, the detected version (see options::detected) or
+// the Bloop Metals ships with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BloopConfig {
+    // e.g. the version a repo pins its sbt-bloop plugin to
+    #[serde(default)]
+    pub bloop_version: Option<String>,
+    // sbt loads sbt-bloop itself, Metals doesn't add it to the build
+    #[serde(default)]
+    pub bloop_sbt_already_installed: Option<bool>,
+}
+
+impl BloopConfig {
+    pub fn from_options(options: Option<&Value>) -> Result<BloopConfig> {
+        let config: BloopConfig = match section(options, Some("metals")) {
+            Value::Null => BloopConfig::default(),
+            metals => serde_json::from_value(metals).context("invalid Metals Bloop settings")?,
+        };
+        config.check()?;
+        Ok(config)
+    }
+
+    pub fn bloop_version(&self) -> Option<&str> {
+        self.bloop_version
+            .as_deref()
+            .map(str::trim)
+            .filter(|version| !version.is_empty())
+    }
+
+    // Metals would only fail the import with a resolution error
+    fn check(&self) -> Result<()> {
+        match self.bloop_version() {
+            Some(version) if Version::parse(version).is_none() => Err(anyhow!(
+                "metals.bloopVersion {version:?} isn't a Bloop version like 1.5.11"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_bloop_config() {
+    let config =
+        |metals: Value| BloopConfig::from_options(Some(&serde_json::json!({ "metals": metals })));
+    let pinned = config(serde_json::json!({
+        "bloopVersion": "1.5.6",
+        "bloopSbtAlreadyInstalled": true,
+    }))
+    .unwrap();
+    assert_eq!(pinned.bloop_version(), Some("1.5.6"));
+    assert_eq!(pinned.bloop_sbt_already_installed, Some(true));
+    // what volt hands over for settings left alone
+    let unset = config(serde_json::json!({ "bloopVersion": "", "bloopSbtAlreadyInstalled": null }))
+        .unwrap();
+    assert_eq!(
+        (unset.bloop_version(), unset.bloop_sbt_already_installed),
+        (None, None)
+    );
+    assert_eq!(
+        BloopConfig::from_options(None).unwrap(),
+        BloopConfig::default()
+    );
+    assert!(config(serde_json::json!({ "bloopVersion": "latest" })).is_err());
+    assert!(config(serde_json::json!({ "bloopSbtAlreadyInstalled": "yes" })).is_err());
+}
+
+// workspace folders holding a build we know. A single Metals serves all of
+// them, it takes every folder of the initialize request as a root of its
+// own, so the plugin only looks at them for detection and build imports
+pub fn scala_roots(folders: &[PathBuf]) -> Vec<&Path> {
+    folders
+        .iter()
+        .filter(|folder| !detect(folder).is_empty())
+        .map(PathBuf::as_path)
+        .collect()
+}
+
+// what the Scala version and build server are detected from: the first
+// folder with a build, otherwise the first one
+pub fn workspace_root(folders: &[PathBuf]) -> Option<&Path> {
+    scala_roots(folders)
+        .first()
+        .copied()
+        .or_else(|| folders.first().map(PathBuf::as_path))
+}
+
+#[test]
+fn test_workspace_root() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    // one folder with an sbt build, one with just a worksheet
+    let folders = [fixtures.join("worksheet"), fixtures.join("sbt-single")];
+    assert_eq!(scala_roots(&folders), [folders[1].as_path()]);
+    assert_eq!(workspace_root(&folders), Some(folders[1].as_path()));
+    let folders = [
+        fixtures.join("mill"),
+        fixtures.join("worksheet"),
+        fixtures.join("sbt-single"),
+    ];
+    assert_eq!(
+        scala_roots(&folders),
+        [folders[0].as_path(), folders[2].as_path()]
+    );
+    assert_eq!(workspace_root(&folders[..1]), Some(folders[0].as_path()));
+    assert_eq!(workspace_root(&[]), None);
+}
+
+// version of gradle on PATH, `gradle -version` prints it as `Gradle 8.5`
+// between two rulers
+pub fn gradle_version(runner: &dyn CommandRunner) -> Option<String> {
+    let output = runner.run("gradle", &["-version"]).ok()?;
+    if !output.success {
+        return None;
+    }
+    let re = Regexp(r"(?m)^Gradle (\S+)").ok()?;
+    re.captures(&output.stdout)
+        .map(|captures| captures[1].to_string())
+}
+
+#[test]
+fn test_gradle() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let tools = detect(&fixtures.join("gradle"));
+    assert_eq!(tools, [BuildTool::Gradle]);
+    assert_eq!(default_bsp(&tools), Some("gradle"));
+    assert!(detect(&fixtures.join("gradle-java")).is_empty());
+
+    let runner = MockRunner::default().with(
+        "gradle -version",
+        true,
+        "\n------------------------------------------------------------\nGradle 8.5\n------------------------------------------------------------\n\nBuild time:   2023-11-29 14:08:57 UTC\nKotlin:       1.9.20\n",
+        "",
+    );
+    assert_eq!(gradle_version(&runner).as_deref(), Some("8.5"));
+    assert_eq!(gradle_version(&MockRunner::default()), None);
+}
+
+#[test]
+fn test_scala_cli() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/scala-cli");
+    let tools = detect(&root);
+    assert_eq!(tools, [BuildTool::ScalaCli]);
+    assert_eq!(default_bsp(&tools), Some("scala-cli"));
+
+    let runner = MockRunner::default().with(
+        "scala-cli version",
+        true,
+        "Scala CLI version: 1.1.2\nScala version (default): 3.3.1\n",
+        "",
+    );
+    assert_eq!(scala_cli_version(&runner).as_deref(), Some("1.1.2"));
+    assert_eq!(scala_cli_version(&MockRunner::default()), None);
+}
+
+#[test]
+fn test_mill_fixture() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mill");
+    let tools = detect(&root);
+    assert_eq!(tools, [BuildTool::Mill]);
+    assert_eq!(default_bsp(&tools), Some("mill-bsp"));
+    assert_eq!(mill_version(&root).as_deref(), Some("0.11.7"));
+
+    let sbt = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sbt-single");
+    assert_eq!(mill_version(&sbt), None);
+    assert_eq!(default_bsp(&[BuildTool::Sbt, BuildTool::Mill]), None);
+}
+
+#[test]
+fn test_detect() {
+    let root = std::env::temp_dir().join("scala-lapce-test-build-tool");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("project")).unwrap();
+    assert!(detect(&root).is_empty());
+
+    std::fs::write(root.join("project/build.properties"), "sbt.version=1.9.9\n").unwrap();
+    assert_eq!(detect(&root), [BuildTool::Sbt]);
+
+    std::fs::write(root.join("build.sc"), "").unwrap();
+    assert_eq!(detect(&root), [BuildTool::Sbt, BuildTool::Mill]);
+}
@@ -0,0 +1,611 @@
+use anyhow::{anyhow, Result};
+use lapce_plugin::{
+    psp_types::lsp_types::{
+        CodeActionKind, CodeActionOrCommand, Command, ExecuteCommandParams,
+        ExecuteCommandRegistrationOptions, InitializeParams, MessageType, Registration,
+        RegistrationParams, Url,
+    },
+    PLUGIN_RPC,
+};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::github::{get_latest_release_for, ReleaseCheck};
+use crate::metals::{self, Channel, MetalsConfig};
+use crate::progress::Progress;
+use crate::runner::SystemRunner;
+use crate::scala::VersionConfig;
+use crate::server::{self, Server};
+use crate::{build_tool, initialize, PluginInfo, State};
+
+pub const RESTART_SERVER: &str = "scala.restartServer";
+pub const RUN_DOCTOR: &str = "scala.runDoctor";
+pub const IMPORT_BUILD: &str = "scala.importBuild";
+pub const SHOW_PLUGIN_INFO: &str = "scala.showPluginInfo";
+pub const OPEN_LOGS: &str = "scala.openLogs";
+pub const ORGANIZE_IMPORTS: &str = "scala.organizeImports";
+pub const CLEAR_CACHE: &str = "scala.clearCache";
+pub const ANALYZE_STACKTRACE: &str = "scala.analyzeStacktrace";
+pub const CONNECT_BUILD_SERVER: &str = "scala.connectBuildServer";
+pub const UPDATE_SERVER: &str = "scala.updateServer";
+
+pub fn all() -> Vec<Command> {
+    [
+        (RESTART_SERVER, "Scala: Restart Metals"),
+        (RUN_DOCTOR, "Scala: Run Metals Doctor"),
+        (IMPORT_BUILD, "Scala: Import Build"),
+        (SHOW_PLUGIN_INFO, "Scala: Show Plugin Info"),
+        (OPEN_LOGS, "Scala: Open Metals Logs"),
+        (ORGANIZE_IMPORTS, "Scala: Organize Imports"),
+        (CLEAR_CACHE, "Scala: Clear Downloaded JDKs and Metals"),
+        (ANALYZE_STACKTRACE, "Scala: Analyze Stacktrace"),
+        (CONNECT_BUILD_SERVER, "Scala: Switch Build Server"),
+        (UPDATE_SERVER, "Scala: Update Metals"),
+    ]
+    .into_iter()
+    .map(|(command, title)| Command {
+        title: title.to_string(),
+        command: command.to_string(),
+        arguments: None,
+    })
+    .collect()
+}
+
+// make the commands show up in the palette
+pub fn register() -> Result<()> {
+    let commands = all().into_iter().map(|command| command.command).collect();
+    let registration = Registration {
+        id: "scala-commands".to_string(),
+        method: "workspace/executeCommand".to_string(),
+        register_options: Some(serde_json::to_value(ExecuteCommandRegistrationOptions {
+            commands,
+            execute_command_options: Default::default(),
+        })?),
+    };
+    server::host_request(
+        "client/registerCapability",
+        serde_json::to_value(RegistrationParams {
+            registrations: vec![registration],
+        })?,
+    )?;
+    Ok(())
+}
+
+pub fn execute(state: &mut State, params: ExecuteCommandParams) -> Result<()> {
+    let result = match params.command.as_str() {
+        RESTART_SERVER => restart_server(state),
+        RUN_DOCTOR => run_doctor(state),
+        IMPORT_BUILD => import_build(state),
+        CONNECT_BUILD_SERVER => connect_build_server(state),
+        ORGANIZE_IMPORTS => organize_imports(state, &params.arguments),
+        ANALYZE_STACKTRACE => analyze_stacktrace(state, &params.arguments),
+        SHOW_PLUGIN_INFO => return show_plugin_info(state),
+        OPEN_LOGS => return open_logs(state),
+        CLEAR_CACHE => return clear_cache(state),
+        UPDATE_SERVER => return update_server(state),
+        command => return Err(anyhow!("unknown command {command}")),
+    };
+    // the others all talk to Metals, a request it didn't answer means it's
+    // gone. Anything else is about what was asked, e.g. no file open
+    match &result {
+        Ok(()) => state.restarts.reset(),
+        Err(e) if server::is_server_failure(e) => state.server_failed(e),
+        Err(_) => {}
+    }
+    result
+}
+
+fn running_server(state: &State) -> Result<&Server> {
+    state
+        .server
+        .as_ref()
+        .ok_or_else(|| anyhow!("Metals hasn't been started yet"))
+}
+
+// Metals answers with the report itself when the client can't render its
+// HTML page, otherwise it opens the page on its own
+fn run_doctor(state: &mut State) -> Result<()> {
+    running_server(state)?;
+    let report = server::execute_command("metals.doctor-run", vec![])?;
+    let message = match report {
+        Value::Null => "Metals doctor is running".to_string(),
+        Value::String(report) => report,
+        report => serde_json::to_string_pretty(&report)?,
+    };
+    PLUGIN_RPC.window_show_message(MessageType::INFO, message);
+    Ok(())
+}
+
+fn import_build(state: &mut State) -> Result<()> {
+    running_server(state)?;
+    // every folder's build, Metals imports them all
+    let mut tools = vec![];
+    for root in build_tool::scala_roots(&state.workspace_folders) {
+        for tool in build_tool::detect(root) {
+            if !tools.contains(&tool) {
+                tools.push(tool);
+            }
+        }
+    }
+    if tools.is_empty() {
+        PLUGIN_RPC.window_show_message(
+            MessageType::WARNING,
+            "no sbt, Mill, Gradle, Maven or scala-cli build found in the workspace, \
+             nothing to import"
+                .to_string(),
+        );
+        return Ok(());
+    }
+
+    // Metals only answers once the import has finished
+    server::execute_command("metals.build-import", vec![])?;
+    let tools: Vec<_> = tools.iter().map(|tool| tool.name()).collect();
+    PLUGIN_RPC.window_show_message(
+        MessageType::INFO,
+        format!("imported {} build", tools.join("/")),
+    );
+    Ok(())
+}
+
+// Metals asks which server to switch to when there's a choice, with only
+// one there's nothing to pick and it reconnects instead
+fn connect_build_server(state: &mut State) -> Result<()> {
+    running_server(state)?;
+    let servers = state
+        .workspace_root
+        .as_deref()
+        .map(build_tool::bsp_servers)
+        .unwrap_or_default();
+    if servers.len() < 2 {
+        server::execute_command("metals.build-connect", vec![])?;
+        let connected = match servers.first() {
+            Some(server) => format!("reconnected to {server}"),
+            None => "reconnected to the build server".to_string(),
+        };
+        PLUGIN_RPC.window_show_message(MessageType::INFO, connected);
+        return Ok(());
+    }
+    PLUGIN_RPC.window_show_message(
+        MessageType::INFO,
+        format!("build servers available: {}", servers.join(", ")),
+    );
+    server::execute_command("metals.bsp-switch", vec![])?;
+    Ok(())
+}
+
+// the file to act on, the one passed along with the command or else the
+// one opened last
+fn target_document(state: &State, arguments: &[Value]) -> Option<Url> {
+    arguments
+        .first()
+        .and_then(Value::as_str)
+        .and_then(|uri| Url::parse(uri).ok())
+        .or_else(|| state.active_document.clone())
+}
+
+fn organize_imports(state: &mut State, arguments: &[Value]) -> Result<()> {
+    running_server(state)?;
+    let uri = target_document(state, arguments)
+        .ok_or_else(|| anyhow!("no Scala file open to organize the imports of"))?;
+    let actions = server::source_actions(uri.clone(), CodeActionKind::SOURCE_ORGANIZE_IMPORTS)?;
+    if actions.is_empty() {
+        PLUGIN_RPC.window_show_message(
+            MessageType::INFO,
+            format!("Metals has no imports to organize in {uri}"),
+        );
+        return Ok(());
+    }
+    for action in actions {
+        match action {
+            CodeActionOrCommand::Command(command) => {
+                server::execute_command(&command.command, command.arguments.unwrap_or_default())?;
+            }
+            CodeActionOrCommand::CodeAction(mut action) => {
+                if action.edit.is_none() && action.command.is_none() {
+                    action = server::resolve_action(action)?;
+                }
+                if let Some(edit) = action.edit {
+                    server::apply_edit(Some(action.title), edit)?;
+                }
+                if let Some(command) = action.command {
+                    server::execute_command(
+                        &command.command,
+                        command.arguments.unwrap_or_default(),
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_target_document() {
+    let opened = Url::parse("file:///home/jane/shop/src/Main.scala").unwrap();
+    let state = State {
+        active_document: Some(opened.clone()),
+        ..State::default()
+    };
+    assert_eq!(target_document(&state, &[]), Some(opened));
+    let passed = "file:///home/jane/shop/src/Cart.scala";
+    assert_eq!(
+        target_document(&state, &[Value::from(passed)]).map(String::from),
+        Some(passed.to_string())
+    );
+    assert_eq!(target_document(&State::default(), &[]), None);
+}
+
+// `at com.example.Cart.total(Cart.scala:12)` lines, as the JVM prints
+// each frame
+fn is_stacktrace(text: &str) -> bool {
+    text.lines()
+        .any(|line| line.trim_start().starts_with("at ") && line.trim_end().ends_with(')'))
+}
+
+// plugins can't read the clipboard or the selection, so the trace is
+// either passed along with the command or pasted into the file opened last
+fn stacktrace_text(state: &State, arguments: &[Value]) -> Result<String> {
+    if let Some(trace) = arguments
+        .first()
+        .and_then(Value::as_str)
+        .filter(|text| is_stacktrace(text))
+    {
+        return Ok(trace.to_string());
+    }
+    let trace = target_document(state, arguments)
+        .and_then(|uri| uri.to_file_path().ok())
+        .and_then(|path| fs::read_to_string(path).ok())
+        .filter(|text| is_stacktrace(text));
+    trace.ok_or_else(|| {
+        anyhow!(
+            "no stack trace to analyze, paste it into a file, open it and run \
+             the command again"
+        )
+    })
+}
+
+// where Metals writes the analyzed trace for clients that don't render its
+// HTML, every frame on it gets a code lens to jump to the source
+fn stacktrace_file(root: &Path) -> PathBuf {
+    root.join(".metals").join("stacktrace.scala")
+}
+
+fn analyze_stacktrace(state: &mut State, arguments: &[Value]) -> Result<()> {
+    running_server(state)?;
+    let trace = stacktrace_text(state, arguments)?;
+    server::execute_command("metals.analyze-stacktrace", vec![Value::from(trace)])?;
+    let file = state.workspace_root.as_deref().map(stacktrace_file);
+    match file.filter(|file| file.is_file()) {
+        Some(file) => {
+            let uri = Url::from_file_path(&file)
+                .map_err(|_| anyhow!("can't open {} in the editor", file.display()))?;
+            server::show_document(uri)
+        }
+        None => {
+            PLUGIN_RPC.window_show_message(
+                MessageType::INFO,
+                "Metals found no frames from this workspace in the stack trace".to_string(),
+            );
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn test_stacktrace_text() {
+    let trace = "\
+java.lang.IllegalStateException: empty cart
+\tat com.example.Cart.total(Cart.scala:12)
+\tat com.example.Main$.main(Main.scala:5)
+";
+    let state = State::default();
+    assert_eq!(
+        stacktrace_text(&state, &[Value::from(trace)]).unwrap(),
+        trace
+    );
+    assert!(stacktrace_text(&state, &[Value::from("empty cart")]).is_err());
+    assert!(stacktrace_text(&state, &[]).is_err());
+
+    let dir = std::env::temp_dir().join("scala-lapce-test-stacktrace");
+    fs::create_dir_all(&dir).unwrap();
+    let pasted = dir.join("trace.txt");
+    fs::write(&pasted, trace).unwrap();
+    let state = State {
+        active_document: Url::from_file_path(&pasted).ok(),
+        ..State::default()
+    };
+    assert_eq!(stacktrace_text(&state, &[]).unwrap(), trace);
+    assert_eq!(
+        stacktrace_file(Path::new("/home/jane/shop")),
+        Path::new("/home/jane/shop/.metals/stacktrace.scala")
+    );
+}
+
+// reuses the launch from initialize, so the JDK and Metals aren't resolved again
+fn restart_server(state: &mut State) -> Result<()> {
+    // started here, not again once the backoff has passed
+    state.restart_at = None;
+    running_server(state)?.restart()?;
+    PLUGIN_RPC.window_show_message(MessageType::INFO, "Metals restarted".to_string());
+    Ok(())
+}
+
+// what changed, `after` being None when the new server isn't one we resolved
+fn update_message(before: Option<&str>, after: Option<&str>) -> String {
+    match (before, after) {
+        (Some(before), Some(after)) if before == after => {
+            format!("Metals {after} is already the latest")
+        }
+        (Some(before), Some(after)) => format!("updated Metals from {before} to {after}"),
+        (None, Some(after)) => format!("started Metals {after}"),
+        (_, None) => "Metals restarted".to_string(),
+    }
+}
+
+#[test]
+fn test_update_message() {
+    assert_eq!(
+        update_message(Some("1.3.0"), Some("1.3.1")),
+        "updated Metals from 1.3.0 to 1.3.1"
+    );
+    assert_eq!(
+        update_message(Some("1.3.1"), Some("1.3.1")),
+        "Metals 1.3.1 is already the latest"
+    );
+    assert_eq!(update_message(None, Some("1.3.1")), "started Metals 1.3.1");
+    assert_eq!(update_message(Some("1.3.0"), None), "Metals restarted");
+}
+
+// looks up the newest Metals past the release cache and starts it in
+// place of the running one. Also the way back after Metals failed to start
+fn update_server(state: &mut State) -> Result<()> {
+    let options = state.initialization_options.as_ref();
+    let config = MetalsConfig::from_options(options)?;
+    if let Some(version) = config.pinned_version() {
+        PLUGIN_RPC.window_show_message(
+            MessageType::INFO,
+            format!(
+                "Metals is pinned to {version} by metalsVersion or \
+                 metalsServerVersionChannel, nothing to update"
+            ),
+        );
+        return Ok(());
+    }
+    if state
+        .server
+        .as_ref()
+        .is_some_and(|server| server.metals_version.is_none())
+    {
+        return Err(anyhow!(
+            "Metals runs from serverPath or metalsLauncherPath, update it there"
+        ));
+    }
+    let before = state
+        .server
+        .as_ref()
+        .and_then(|server| server.metals_version.clone());
+    // snapshots are looked up fresh on every start anyway, a release lookup
+    // refreshes the cache initialize then reads it from
+    if config.metals_server_version_channel != Channel::Snapshot {
+        let check = ReleaseCheck {
+            ttl: Duration::ZERO,
+            ..ReleaseCheck::from_options(options)?
+        };
+        let latest = get_latest_release_for(metals::METALS_REPO, &check)?;
+        let latest = latest.trim_start_matches('v');
+        if before.as_deref() == Some(latest) {
+            PLUGIN_RPC.window_show_message(
+                MessageType::INFO,
+                update_message(Some(latest), Some(latest)),
+            );
+            return Ok(());
+        }
+    }
+
+    let previous = state.server.take();
+    state.restart_at = None;
+    if let Some(server) = &previous {
+        // a Metals that may still run is kept, rather than a second one
+        if let Err(e) = server.stop() {
+            state.server = previous;
+            return Err(e.context("Metals may still be running, reload the window to update it"));
+        }
+    }
+    let params = InitializeParams {
+        initialization_options: state.initialization_options.clone(),
+        ..InitializeParams::default()
+    };
+    let updating = Progress::begin("Updating Metals");
+    let result = initialize(params, state.workspace_root.as_deref());
+    updating.end(None);
+    if let Some(server) = fallback_server(&result, previous) {
+        server.start();
+        state.server = Some(server);
+    }
+    match result {
+        Ok(Some(server)) => state.server = Some(server),
+        // initialize already said why
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(e),
+    }
+    let after = state
+        .server
+        .as_ref()
+        .and_then(|server| server.metals_version.as_deref());
+    PLUGIN_RPC.window_show_message(MessageType::INFO, update_message(before.as_deref(), after));
+    Ok(())
+}
+
+// the Metals to start again when the update brought none up, the previous
+// one was stopped for it but is better than none
+fn fallback_server(result: &Result<Option<Server>>, previous: Option<Server>) -> Option<Server> {
+    match result {
+        Ok(Some(_)) => None,
+        Ok(None) | Err(_) => previous,
+    }
+}
+
+#[test]
+fn test_fallback_server() {
+    let server = |version: &str| Server {
+        server_uri: Url::parse("urn:metals").unwrap(),
+        server_args: vec![],
+        document_selector: vec![],
+        options: None,
+        base_options: None,
+        installs: vec![],
+        metals_version: Some(version.to_string()),
+    };
+    let version = |server: Option<Server>| server.and_then(|server| server.metals_version);
+    assert_eq!(
+        version(fallback_server(&Ok(None), Some(server("1.3.0")))),
+        Some("1.3.0".to_string())
+    );
+    assert_eq!(
+        version(fallback_server(
+            &Err(anyhow!("offline")),
+            Some(server("1.3.0"))
+        )),
+        Some("1.3.0".to_string())
+    );
+    assert!(fallback_server(&Ok(Some(server("1.3.1"))), Some(server("1.3.0"))).is_none());
+    assert!(fallback_server(&Ok(None), None).is_none());
+}
+
+// doesn't need Metals, so it also works when the server failed to start
+fn show_plugin_info(state: &mut State) -> Result<()> {
+    let version_config = VersionConfig::from_options(state.initialization_options.as_ref())?;
+    let info = PluginInfo::detect(
+        state.workspace_root.as_deref(),
+        &version_config,
+        &SystemRunner,
+    );
+    // fresh, so this also corrects what initialize got from the cache
+    info.remember(state.workspace_root.as_deref(), &version_config);
+    PLUGIN_RPC.window_show_message(MessageType::INFO, info.report());
+    Ok(())
+}
+
+// everything the plugin downloaded or cached, relative to its working directory
+const CACHE_DIRS: [&str; 4] = ["jdk", "metals", "coursier", "cache"];
+
+// empty the cache directories except for what `in_use` lies in, returning
+// how many entries were removed
+fn clear_dirs(dirs: &[PathBuf], in_use: &[PathBuf]) -> Result<usize> {
+    let mut removed = 0;
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if in_use.iter().any(|used| used.starts_with(&path)) {
+                continue;
+            }
+            if path.is_dir() {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_file(&path)?;
+            }
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+// what the running Metals needs is kept, the rest is downloaded again
+// when it's next needed
+fn clear_cache(state: &mut State) -> Result<()> {
+    let in_use = state
+        .server
+        .as_ref()
+        .map(|server| server.installs.clone())
+        .unwrap_or_default();
+    let dirs: Vec<_> = CACHE_DIRS.iter().map(PathBuf::from).collect();
+    let removed = clear_dirs(&dirs, &in_use)?;
+    let kept = if in_use.is_empty() {
+        String::new()
+    } else {
+        ", kept the JDK and Metals launcher in use".to_string()
+    };
+    PLUGIN_RPC.window_show_message(
+        MessageType::INFO,
+        format!("removed {removed} cached downloads{kept}"),
+    );
+    Ok(())
+}
+
+#[test]
+fn test_clear_dirs() {
+    let root = std::env::temp_dir().join("scala-lapce-test-clear-dirs");
+    let _ = fs::remove_dir_all(&root);
+    let (jdk, metals) = (root.join("jdk"), root.join("metals"));
+    let home = jdk.join("temurin-21.0.2+13-linux-x64/jdk-21.0.2+13");
+    fs::create_dir_all(&home).unwrap();
+    fs::create_dir_all(jdk.join("temurin-17.0.10+7-linux-x64")).unwrap();
+    fs::write(
+        jdk.join("OpenJDK17U-jdk_x64_linux_hotspot_17.0.10_7.tar.gz"),
+        b"",
+    )
+    .unwrap();
+    fs::create_dir_all(metals.join("metals_2.13-1.3.0")).unwrap();
+
+    let removed = clear_dirs(
+        &[jdk.clone(), metals.clone(), root.join("cache")],
+        &[home.clone()],
+    )
+    .unwrap();
+    assert_eq!(removed, 3);
+    assert!(home.exists());
+    assert!(!jdk.join("temurin-17.0.10+7-linux-x64").exists());
+    assert!(!metals.join("metals_2.13-1.3.0").exists());
+}
+
+// Metals keeps its log in the workspace it serves
+fn metals_log(root: &Path) -> PathBuf {
+    root.join(".metals").join("metals.log")
+}
+
+fn open_logs(state: &mut State) -> Result<()> {
+    let root = state
+        .workspace_root
+        .as_deref()
+        .ok_or_else(|| anyhow!("no workspace open, Metals only logs to a workspace"))?;
+    let log = metals_log(root);
+    if !log.is_file() {
+        PLUGIN_RPC.window_show_message(
+            MessageType::INFO,
+            format!(
+                "no Metals log at {} yet, Metals hasn't started in this workspace",
+                log.display()
+            ),
+        );
+        return Ok(());
+    }
+    let uri = Url::from_file_path(&log)
+        .map_err(|_| anyhow!("can't open {} in the editor", log.display()))?;
+    server::show_document(uri)
+}
+
+#[test]
+fn test_metals_log() {
+    assert_eq!(
+        metals_log(Path::new("/home/jane/shop")),
+        Path::new("/home/jane/shop/.metals/metals.log")
+    );
+}
+
+#[test]
+fn test_all_commands() {
+    let commands = all();
+    let mut names: Vec<_> = commands.iter().map(|c| c.command.as_str()).collect();
+    assert!(names.iter().all(|name| name.starts_with("scala.")));
+    names.sort_unstable();
+    names.dedup();
+    assert_eq!(names.len(), commands.len());
+    assert!(names.contains(&RUN_DOCTOR));
+}
@@ -0,0 +1,424 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use wasi_experimental_http::Response;
+
+use crate::progress::Progress;
+use crate::proxy::ProxySettings;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// how long a download may go without receiving anything, and a release
+// lookup may keep retrying
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// set once from the configuration in initialize
+static TIMEOUT_SECS: AtomicU64 = AtomicU64::new(DEFAULT_TIMEOUT.as_secs());
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TimeoutConfig {
+    #[serde(default = "default_timeout_seconds")]
+    download_timeout_seconds: u64,
+}
+
+fn default_timeout_seconds() -> u64 {
+    DEFAULT_TIMEOUT.as_secs()
+}
+
+pub fn timeout_from_options(options: Option<&Value>) -> Result<Duration> {
+    match options {
+        Some(options) => {
+            let config: TimeoutConfig = serde_json::from_value(options.clone())
+                .context("invalid downloadTimeoutSeconds")?;
+            Ok(Duration::from_secs(config.download_timeout_seconds))
+        }
+        None => Ok(DEFAULT_TIMEOUT),
+    }
+}
+
+pub fn set_timeout(timeout: Duration) {
+    TIMEOUT_SECS.store(timeout.as_secs(), Ordering::Relaxed);
+}
+
+fn timeout() -> Duration {
+    Duration::from_secs(TIMEOUT_SECS.load(Ordering::Relaxed))
+}
+
+#[test]
+fn test_timeout_from_options() {
+    let options = serde_json::json!({ "downloadTimeoutSeconds": 30 });
+    assert_eq!(
+        timeout_from_options(Some(&options)).unwrap(),
+        Duration::from_secs(30)
+    );
+    let options = serde_json::json!({ "jdkVersion": "21" });
+    assert_eq!(
+        timeout_from_options(Some(&options)).unwrap(),
+        DEFAULT_TIMEOUT
+    );
+    let options = serde_json::json!({ "downloadTimeoutSeconds": "soon" });
+    assert!(timeout_from_options(Some(&options)).is_err());
+}
+
+// the response body as a plain reader
+struct Body(Response);
+
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0
+            .body_read(buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{e:?}")))
+    }
+}
+
+// copy `body` to `out` one chunk at a time, so a JDK archive never has to
+// fit in the sandbox's memory. Returns the number of bytes copied. Fails
+// when a read waited `stall` or longer, however long the whole transfer
+// takes, so a JDK still gets through a slow link. A read that blocks
+// forever can't be interrupted from inside the sandbox
+fn stream(
+    body: &mut impl Read,
+    out: &mut impl Write,
+    stall: Duration,
+    mut on_progress: impl FnMut(u64),
+) -> io::Result<u64> {
+    let mut chunk = vec![0; CHUNK_SIZE];
+    let mut copied = 0;
+    loop {
+        let started = Instant::now();
+        let read = match body.read(&mut chunk) {
+            Ok(_) if started.elapsed() >= stall => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "nothing received for {} seconds after {copied} bytes",
+                        stall.as_secs()
+                    ),
+                ));
+            }
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        out.write_all(&chunk[..read])?;
+        copied += read as u64;
+        on_progress(copied);
+    }
+    out.flush()?;
+    Ok(copied)
+}
+
+#[cfg(test)]
+struct MockBody {
+    remaining: u64,
+}
+
+#[cfg(test)]
+impl Read for MockBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = buf.len().min(self.remaining as usize);
+        buf[..read].fill(0x5a);
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct CountingWriter {
+    written: u64,
+    largest_write: usize,
+}
+
+#[cfg(test)]
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len() as u64;
+        self.largest_write = self.largest_write.max(buf.len());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_stream_large_body() {
+    // about the size of a JDK archive
+    let size = 200 * 1024 * 1024 + 17;
+    let mut body = MockBody { remaining: size };
+    let mut out = CountingWriter::default();
+    let mut reports = 0;
+    let stall = Duration::from_secs(60);
+    let copied = stream(&mut body, &mut out, stall, |_| reports += 1).unwrap();
+    assert_eq!(copied, size);
+    assert_eq!(out.written, size);
+    // the body went through in chunks instead of being collected first
+    assert!(out.largest_write <= CHUNK_SIZE);
+    assert_eq!(reports, size.div_ceil(CHUNK_SIZE as u64));
+}
+
+#[test]
+fn test_stream_times_out() {
+    // a byte every 20ms, then nothing for 150ms
+    struct SlowBody(u32);
+    impl Read for SlowBody {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0 += 1;
+            let wait = if self.0 == 10 { 150 } else { 20 };
+            std::thread::sleep(Duration::from_millis(wait));
+            buf[0] = 0;
+            Ok(1)
+        }
+    }
+
+    let started = Instant::now();
+    let stall = Duration::from_millis(100);
+    let e = stream(
+        &mut SlowBody(0),
+        &mut CountingWriter::default(),
+        stall,
+        |_| {},
+    )
+    .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    // slow all along, but only the stall counts
+    assert!(started.elapsed() > stall);
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+// where a download goes until it's complete, so an interrupted one is
+// never mistaken for the real file
+fn partial_path(dest: &Path) -> PathBuf {
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(".part");
+    PathBuf::from(partial)
+}
+
+// straight through the host http api lapce_plugin::Http wraps, that one
+// can't send headers or hand out the body a chunk at a time
+fn get(url: &str, headers: &[(&str, String)]) -> Result<Response> {
+    let mut request = http::Request::get(url);
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+    wasi_experimental_http::request(request.body(None)?).map_err(|e| {
+        // Lapce makes the request, with the proxy from its own environment
+        match ProxySettings::from_env().https {
+            Some(proxy) => anyhow!(
+                "GET {url} through the proxy {}:{} failed: {e:?}",
+                proxy.host,
+                proxy.port
+            ),
+            None => anyhow!("GET {url} failed: {e:?}"),
+        }
+    })
+}
+
+// what to do with the bytes a previous, interrupted download left behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    // the server sent the rest of the file
+    Append(u64),
+    // the server ignored the range and sent all of it
+    Restart,
+    // the range was no good, most likely the partial file is broken
+    Refetch,
+}
+
+fn resume(offset: u64, status: u16) -> Resume {
+    match (offset, status) {
+        (0, _) => Resume::Restart,
+        (_, 206) => Resume::Append(offset),
+        (_, 416) => Resume::Refetch,
+        _ => Resume::Restart,
+    }
+}
+
+// full size from `Content-Range: bytes 1000-1999/2000`
+fn content_range_total(content_range: &str) -> Option<u64> {
+    content_range.rsplit_once('/')?.1.trim().parse().ok()
+}
+
+#[test]
+fn test_resume() {
+    assert_eq!(resume(0, 200), Resume::Restart);
+    assert_eq!(resume(1000, 206), Resume::Append(1000));
+    assert_eq!(resume(1000, 200), Resume::Restart);
+    assert_eq!(resume(1000, 416), Resume::Refetch);
+    assert_eq!(content_range_total("bytes 1000-1999/2000"), Some(2000));
+    assert_eq!(content_range_total("bytes 1000-1999/*"), None);
+    assert_eq!(content_range_total(""), None);
+}
+
+// downloads a JDK or Coursier, reporting progress as the body comes in and
+// picking up where an earlier attempt stopped if the server allows it
+pub fn fetch(url: &str, dest: &Path) -> Result<()> {
+    let partial = partial_path(dest);
+    let offset = fs::metadata(&partial).map(|meta| meta.len()).unwrap_or(0);
+    let range = if offset > 0 {
+        vec![("range", format!("bytes={offset}-"))]
+    } else {
+        vec![]
+    };
+    let mut resp = get(url, &range)?;
+    let mut resume = resume(offset, resp.status_code.as_u16());
+    if resume == Resume::Refetch {
+        fs::remove_file(&partial)?;
+        resp = get(url, &[])?;
+        resume = Resume::Restart;
+    }
+    if !resp.status_code.is_success() {
+        return Err(anyhow!("GET {url} returned {}", resp.status_code));
+    }
+
+    let length: Option<u64> = resp
+        .header_get("content-length")
+        .ok()
+        .and_then(|length| length.trim().parse().ok());
+    let (offset, mut out) = match resume {
+        Resume::Append(offset) => (offset, OpenOptions::new().append(true).open(&partial)?),
+        _ => (0, File::create(&partial)?),
+    };
+    let total = match resume {
+        Resume::Append(offset) => resp
+            .header_get("content-range")
+            .ok()
+            .and_then(|range| content_range_total(&range))
+            .or(length.map(|length| offset + length)),
+        _ => length,
+    };
+
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    let mut progress = Progress::begin(&format!("Downloading {name}"));
+    let mut writer = BufWriter::new(&mut out);
+    // on error the partial file stays, the next attempt continues from it
+    stream(&mut Body(resp), &mut writer, timeout(), |done| {
+        progress.report(offset + done, total)
+    })
+    .with_context(|| format!("download of {url} failed"))?;
+    drop(writer);
+
+    let size = out.metadata()?.len();
+    match total {
+        Some(total) if size < total => {
+            return Err(anyhow!(
+                "download of {url} stopped at {size} of {total} bytes"
+            ));
+        }
+        Some(total) if size > total => {
+            fs::remove_file(&partial)?;
+            return Err(anyhow!(
+                "download of {url} is {size} bytes, expected {total}"
+            ));
+        }
+        _ => {}
+    }
+    fs::rename(&partial, dest)?;
+    progress.end(Some(format!("Downloaded {name}")));
+    Ok(())
+}
+
+pub fn fetch_text(url: &str) -> Result<String> {
+    let resp = get(url, &[])?;
+    if !resp.status_code.is_success() {
+        return Err(anyhow!("GET {url} returned {}", resp.status_code));
+    }
+    read_text(resp, url)
+}
+
+// the whole body, given up on after the same stall as a download
+fn read_text(resp: Response, url: &str) -> Result<String> {
+    let mut body = vec![];
+    stream(&mut Body(resp), &mut body, timeout(), |_| {})
+        .with_context(|| format!("reading {url} failed"))?;
+    Ok(String::from_utf8(body)?)
+}
+
+// a response whatever its status, with the headers that were asked for,
+// for APIs that want to know who's asking and explain their errors in headers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextResponse {
+    pub status: http::StatusCode,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+impl TextResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+pub fn get_text(
+    url: &str,
+    headers: &[(&str, String)],
+    response_headers: &[&str],
+) -> Result<TextResponse> {
+    let resp = get(url, headers)?;
+    let headers = response_headers
+        .iter()
+        .filter_map(|name| Some((name.to_string(), resp.header_get(name).ok()?)))
+        .collect();
+    Ok(TextResponse {
+        status: resp.status_code,
+        headers,
+        body: read_text(resp, url)?,
+    })
+}
+
+// vendors publish a checksum file next to each asset
+pub fn fetch_sha256(checksum_url: &str) -> Result<String> {
+    let body = fetch_text(checksum_url)?;
+    parse_sha256_file(&body).ok_or_else(|| anyhow!("malformed checksum file at {checksum_url}"))
+}
+
+// the checksum files look like `<hex digest>  <filename>`
+fn parse_sha256_file(contents: &str) -> Option<String> {
+    let digest = contents.split_whitespace().next()?;
+    if digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(digest.to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[test]
+fn test_parse_sha256_file() {
+    let digest = "a".repeat(64);
+    assert_eq!(
+        parse_sha256_file(&format!(
+            "{digest}  OpenJDK21U-jdk_x64_linux_hotspot_21.0.2_13.tar.gz\n"
+        )),
+        Some(digest)
+    );
+    assert_eq!(parse_sha256_file("<html>not found</html>"), None);
+    assert_eq!(parse_sha256_file(""), None);
+}
+
+#[test]
+fn test_sha256_file() {
+    let path = std::env::temp_dir().join("scala-lapce-test-sha256");
+    fs::write(&path, b"abc").unwrap();
+    assert_eq!(
+        sha256_file(&path).unwrap(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
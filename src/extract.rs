@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Context, Result};
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+// unpack a downloaded archive into `dest`, picking the format from its extension
+pub fn unpack(archive: &Path, dest: &Path) -> Result<()> {
+    let name = archive.to_string_lossy();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        untar_gz(archive, dest)
+    } else if name.ends_with(".zip") {
+        unzip(archive, dest)
+    } else {
+        Err(anyhow!("don't know how to unpack {name}"))
+    }
+}
+
+// unpack a .tar.gz archive into `dest`, keeping its top-level directory
+pub fn untar_gz(archive: &Path, dest: &Path) -> Result<()> {
+    let file = File::open(archive)
+        .with_context(|| format!("failed to open archive {}", archive.display()))?;
+    let mut tarball = tar::Archive::new(GzDecoder::new(file));
+    tarball
+        .unpack(dest)
+        .with_context(|| format!("failed to unpack {}", archive.display()))?;
+    Ok(())
+}
+
+pub fn unzip(archive: &Path, dest: &Path) -> Result<()> {
+    let file = File::open(archive)
+        .with_context(|| format!("failed to open archive {}", archive.display()))?;
+    zip::ZipArchive::new(file)?
+        .extract(dest)
+        .with_context(|| format!("failed to unpack {}", archive.display()))?;
+    Ok(())
+}
+
+// decompress a single gzipped file, e.g. a native launcher
+pub fn gunzip(archive: &Path, dest: &Path) -> Result<()> {
+    let file = File::open(archive)
+        .with_context(|| format!("failed to open archive {}", archive.display()))?;
+    let mut out = File::create(dest)?;
+    io::copy(&mut GzDecoder::new(file), &mut out)
+        .with_context(|| format!("failed to decompress {}", archive.display()))?;
+    Ok(())
+}
+
+#[test]
+fn test_untar_gz() {
+    use flate2::{write::GzEncoder, Compression};
+
+    let dir = std::env::temp_dir().join("scala-lapce-test-untar-gz");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let archive = dir.join("jdk.tar.gz");
+    let mut builder = tar::Builder::new(GzEncoder::new(
+        File::create(&archive).unwrap(),
+        Compression::default(),
+    ));
+    let java = b"#!/bin/sh\n";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(java.len() as u64);
+    header.set_mode(0o755);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "jdk-21.0.2+13/bin/java", &java[..])
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+
+    unpack(&archive, &dir).unwrap();
+    assert!(dir.join("jdk-21.0.2+13/bin/java").exists());
+}
+
+#[test]
+fn test_unzip() {
+    use std::io::Write;
+
+    let dir = std::env::temp_dir().join("scala-lapce-test-unzip");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let archive = dir.join("jdk.zip");
+    let mut writer = zip::ZipWriter::new(File::create(&archive).unwrap());
+    writer
+        .start_file("jdk-21.0.2+13/bin/java.exe", Default::default())
+        .unwrap();
+    writer.write_all(b"MZ").unwrap();
+    writer.finish().unwrap();
+
+    unpack(&archive, &dir).unwrap();
+    assert!(dir.join("jdk-21.0.2+13/bin/java.exe").exists());
+    assert!(unpack(&dir.join("jdk.msi"), &dir).is_err());
+}
+
+#[test]
+fn test_gunzip() {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    let dir = std::env::temp_dir().join("scala-lapce-test-gunzip");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let archive = dir.join("cs.gz");
+    let mut encoder = GzEncoder::new(File::create(&archive).unwrap(), Compression::default());
+    encoder.write_all(b"\x7fELF").unwrap();
+    encoder.finish().unwrap();
+
+    gunzip(&archive, &dir.join("cs")).unwrap();
+    assert_eq!(std::fs::read(dir.join("cs")).unwrap(), b"\x7fELF");
+}
@@ -0,0 +1,670 @@
+use anyhow::{anyhow, Context, Result};
+use lapce_plugin::{
+    psp_types::lsp_types::{MessageType, Url},
+    PLUGIN_RPC,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env::var as env_var;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::download::TextResponse;
+use crate::version::Version;
+use crate::{download, logging};
+
+const ATTEMPTS: u32 = 3;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+// last release seen for each repo, relative to the plugin working directory
+const CACHE_FILE: &str = "cache/releases.json";
+
+// call `f` until it succeeds, doubling the delay between attempts, as
+// long as there's time left before the deadline
+fn retry<T>(
+    attempts: u32,
+    initial_backoff: Duration,
+    deadline: Instant,
+    mut f: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    let mut backoff = initial_backoff;
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            // trying again only uses up more of the limit
+            Err(e) if e.is::<RateLimited>() => return Err(e),
+            Err(e) if attempt >= attempts => {
+                return Err(e.context(format!("giving up after {attempts} attempts")))
+            }
+            Err(e) if Instant::now() + backoff >= deadline => {
+                return Err(e.context(format!("timed out after {attempt} attempts")))
+            }
+            Err(_) => {
+                sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_retry() {
+    let mut calls = 0;
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let result = retry(3, Duration::ZERO, deadline, || {
+        calls += 1;
+        if calls < 3 {
+            Err(anyhow::anyhow!("connection reset"))
+        } else {
+            Ok(calls)
+        }
+    });
+    assert_eq!(result.unwrap(), 3);
+
+    let mut calls = 0;
+    let result: Result<()> = retry(3, Duration::ZERO, deadline, || {
+        calls += 1;
+        Err(anyhow::anyhow!("connection reset"))
+    });
+    assert_eq!(calls, 3);
+    assert!(format!("{:#}", result.unwrap_err()).contains("giving up after 3 attempts"));
+
+    // no point waiting out a backoff that ends past the deadline
+    let mut calls = 0;
+    let started = Instant::now();
+    let result: Result<()> = retry(3, Duration::from_secs(3600), started, || {
+        calls += 1;
+        Err(anyhow::anyhow!("connection timed out"))
+    });
+    assert_eq!(calls, 1);
+    assert!(format!("{:#}", result.unwrap_err()).contains("timed out after 1 attempts"));
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheEntry {
+    version: String,
+    // seconds since the unix epoch
+    checked_at: u64,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self, now: u64, ttl: Duration) -> bool {
+        now.saturating_sub(self.checked_at) < ttl.as_secs()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+fn read_cache(path: &Path) -> BTreeMap<String, CacheEntry> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|cache| serde_json::from_str(&cache).ok())
+        .unwrap_or_default()
+}
+
+fn remember(path: &Path, repo: &str, version: &str, now: u64) -> Result<()> {
+    let mut cache = read_cache(path);
+    cache.insert(
+        repo.to_string(),
+        CacheEntry {
+            version: version.to_string(),
+            checked_at: now,
+        },
+    );
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&cache)?)?;
+    Ok(())
+}
+
+#[test]
+fn test_release_cache() {
+    let path = std::env::temp_dir().join("scala-lapce-test-release-cache/releases.json");
+    let _ = fs::remove_file(&path);
+    assert_eq!(read_cache(&path).get("scalameta/metals"), None);
+
+    remember(&path, "scalameta/metals", "v1.3.0", 100).unwrap();
+    remember(&path, "adoptium/temurin21-binaries", "jdk-21.0.2+13", 100).unwrap();
+    remember(&path, "scalameta/metals", "v1.3.1", 200).unwrap();
+    let cache = read_cache(&path);
+    assert_eq!(
+        cache.get("scalameta/metals"),
+        Some(&CacheEntry {
+            version: "v1.3.1".to_string(),
+            checked_at: 200,
+        })
+    );
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn test_cache_entry_is_fresh() {
+    let entry = CacheEntry {
+        version: "v1.3.1".to_string(),
+        checked_at: 1_000,
+    };
+    let day = Duration::from_secs(24 * 60 * 60);
+    assert!(entry.is_fresh(1_000 + 60, day));
+    assert!(!entry.is_fresh(1_000 + day.as_secs(), day));
+    // a ttl of 0 always checks GitHub
+    assert!(!entry.is_fresh(1_000, Duration::ZERO));
+    // clock went backwards
+    assert!(entry.is_fresh(10, day));
+}
+
+const API_ENDPOINT: &str = "https://api.github.com/";
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct ReleaseCheckConfig {
+    github_token: Option<String>,
+    release_cache_ttl_hours: u64,
+    github_api_url: Option<String>,
+}
+
+impl Default for ReleaseCheckConfig {
+    fn default() -> Self {
+        Self {
+            github_token: None,
+            release_cache_ttl_hours: 24,
+            github_api_url: None,
+        }
+    }
+}
+
+// how releases are looked up on GitHub
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReleaseCheck {
+    // a token lifts the GitHub API rate limit from 60 to 5000 requests an hour
+    pub token: Option<String>,
+    // how long a looked up release is trusted before asking GitHub again
+    pub ttl: Duration,
+    // how long to keep retrying, a single request to GitHub can't be cut
+    // short from here
+    pub timeout: Duration,
+    // a GitHub Enterprise API, e.g. https://github.example.com/api/v3/,
+    // github.com's when None
+    pub api_url: Option<String>,
+}
+
+impl ReleaseCheck {
+    // the githubToken setting wins over GITHUB_TOKEN from the environment
+    pub fn from_options(options: Option<&Value>) -> Result<ReleaseCheck> {
+        let config: ReleaseCheckConfig = match options {
+            Some(options) => serde_json::from_value(options.clone())
+                .context("invalid GitHub release check configuration")?,
+            None => ReleaseCheckConfig::default(),
+        };
+        let non_empty = |token: String| (!token.trim().is_empty()).then_some(token);
+        Ok(ReleaseCheck {
+            token: config
+                .github_token
+                .and_then(non_empty)
+                .or_else(|| env_var("GITHUB_TOKEN").ok().and_then(non_empty)),
+            ttl: Duration::from_secs(config.release_cache_ttl_hours * 60 * 60),
+            timeout: download::timeout_from_options(options)?,
+            api_url: config
+                .github_api_url
+                .map(|url| url.trim().trim_end_matches('/').to_string())
+                .filter(|url| !url.is_empty())
+                .map(|url| {
+                    Url::parse(&url)
+                        .map(|_| format!("{url}/"))
+                        .with_context(|| format!("githubApiUrl {url:?} isn't a URL"))
+                })
+                .transpose()?,
+        })
+    }
+
+    fn api(&self) -> &str {
+        self.api_url.as_deref().unwrap_or(API_ENDPOINT)
+    }
+}
+
+#[test]
+fn test_release_check_from_options() {
+    let env_token = env_var("GITHUB_TOKEN")
+        .ok()
+        .filter(|t| !t.trim().is_empty());
+    let options = serde_json::json!({
+        "githubToken": "ghp_from_config",
+        "releaseCacheTtlHours": 1,
+    });
+    assert_eq!(
+        ReleaseCheck::from_options(Some(&options)).unwrap(),
+        ReleaseCheck {
+            token: Some("ghp_from_config".to_string()),
+            ttl: Duration::from_secs(60 * 60),
+            timeout: download::DEFAULT_TIMEOUT,
+            api_url: None,
+        }
+    );
+    // an empty setting is the volt default, not an actual token
+    let options = serde_json::json!({ "githubToken": "" });
+    let check = ReleaseCheck::from_options(Some(&options)).unwrap();
+    assert_eq!(check.token, env_token);
+    assert_eq!(check.ttl, Duration::from_secs(24 * 60 * 60));
+    assert_eq!(check.api(), "https://api.github.com/");
+
+    let options = serde_json::json!({ "githubApiUrl": "https://github.example.com/api/v3" });
+    let check = ReleaseCheck::from_options(Some(&options)).unwrap();
+    assert_eq!(check.api(), "https://github.example.com/api/v3/");
+    let options = serde_json::json!({ "githubApiUrl": "github.example.com" });
+    assert!(ReleaseCheck::from_options(Some(&options)).is_err());
+}
+
+// how long a rate limit that's about to reset is waited out for
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(10);
+
+const RATE_LIMIT_HEADERS: [&str; 3] = ["x-ratelimit-remaining", "x-ratelimit-reset", "retry-after"];
+
+// GitHub turned the request down for going over the rate limit
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RateLimited {
+    // until the limit resets, when GitHub says
+    wait: Option<Duration>,
+    authenticated: bool,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GitHub API rate limit exceeded")?;
+        match self.wait.map(|wait| wait.as_secs().div_ceil(60)) {
+            Some(0 | 1) => write!(f, ", it resets within a minute")?,
+            Some(minutes) => write!(f, ", it resets in {minutes} minutes")?,
+            None => {}
+        }
+        if !self.authenticated {
+            write!(f, ", set githubToken or GITHUB_TOKEN for a higher limit")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+// the primary limit is a 403 or 429 with no requests remaining and the
+// time it resets at, the secondary one a Retry-After in seconds
+fn rate_limit(response: &TextResponse, now: u64, authenticated: bool) -> Option<RateLimited> {
+    if ![403, 429].contains(&response.status.as_u16()) {
+        return None;
+    }
+    let seconds = |name: &str| response.header(name)?.trim().parse::<u64>().ok();
+    let wait = match (seconds("retry-after"), seconds("x-ratelimit-remaining")) {
+        (Some(retry_after), _) => Some(retry_after),
+        (None, Some(0)) => seconds("x-ratelimit-reset").map(|reset| reset.saturating_sub(now)),
+        // a 403 for anything else, like a repo the token can't see
+        _ => return None,
+    };
+    Some(RateLimited {
+        wait: wait.map(Duration::from_secs),
+        authenticated,
+    })
+}
+
+#[test]
+fn test_rate_limit() {
+    let response = |status: u16, headers: &[(&str, &str)]| TextResponse {
+        status: http::StatusCode::from_u16(status).unwrap(),
+        headers: headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        body: r#"{"message": "API rate limit exceeded for 203.0.113.7."}"#.to_string(),
+    };
+    let limited = response(
+        403,
+        &[
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-reset", "1700001380"),
+        ],
+    );
+    let limit = rate_limit(&limited, 1_700_000_000, false).unwrap();
+    assert_eq!(limit.wait, Some(Duration::from_secs(1380)));
+    assert_eq!(
+        limit.to_string(),
+        "GitHub API rate limit exceeded, it resets in 23 minutes, set githubToken or \
+         GITHUB_TOKEN for a higher limit"
+    );
+    let secondary = response(429, &[("retry-after", "5")]);
+    assert_eq!(
+        rate_limit(&secondary, 1_700_000_000, true),
+        Some(RateLimited {
+            wait: Some(Duration::from_secs(5)),
+            authenticated: true,
+        })
+    );
+    let forbidden = response(403, &[("x-ratelimit-remaining", "4999")]);
+    assert_eq!(rate_limit(&forbidden, 1_700_000_000, true), None);
+    assert_eq!(rate_limit(&response(200, &[]), 1_700_000_000, false), None);
+}
+
+// GitHub answered with an error status that isn't a rate limit
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rejected {
+    url: String,
+    status: http::StatusCode,
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GET {} returned {}", self.url, self.status)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+fn is_unauthorized(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<Rejected>()
+        .is_some_and(|rejected| rejected.status == http::StatusCode::UNAUTHORIZED)
+}
+
+#[test]
+fn test_is_unauthorized() {
+    let rejected = |status: u16| {
+        anyhow::Error::from(Rejected {
+            url: "https://api.github.com/repos/scalameta/metals/releases".to_string(),
+            status: http::StatusCode::from_u16(status).unwrap(),
+        })
+    };
+    assert!(is_unauthorized(&rejected(401)));
+    assert!(is_unauthorized(
+        &rejected(401).context("failed to query latest release")
+    ));
+    assert!(!is_unauthorized(&rejected(404)));
+    // only the status counts, not what the message says
+    assert!(!is_unauthorized(&anyhow!(
+        "GET https://example.com returned 401"
+    )));
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    draft: bool,
+}
+
+// sort key of a stable release tag, `v1.3.0`, `1.3.0` or Adoptium's
+// `jdk-21.0.2+13` and `jdk-21+35`, the build number breaking ties. Tags with
+// a pre-release suffix like `-RC1` or `-ea` aren't stable and get None
+fn release_version(tag: &str) -> Option<Version> {
+    let tag = tag.trim();
+    let version = tag
+        .strip_prefix('v')
+        .or_else(|| tag.strip_prefix("jdk-"))
+        .unwrap_or(tag);
+    Version::parse(version).filter(|version| {
+        version.pre_release.is_none()
+            && (version.build.is_none() || version.build_number().is_some())
+    })
+}
+
+// the highest stable release, skipping drafts, pre-releases and tags that
+// aren't versions at all (nightlies and the like)
+fn latest_stable(releases: &[Release]) -> Option<&str> {
+    releases
+        .iter()
+        .filter(|release| !release.prerelease && !release.draft)
+        .filter_map(|release| Some((release_version(&release.tag_name)?, &release.tag_name)))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, tag)| tag.as_str())
+}
+
+#[test]
+fn test_latest_stable() {
+    let release = |tag: &str, prerelease: bool| Release {
+        tag_name: tag.to_string(),
+        prerelease,
+        draft: false,
+    };
+    let metals = [
+        release("v1.3.1-RC1", false),
+        release("v1.2.2", false),
+        release("v1.3.0", false),
+        release("nightly", false),
+        release("v1.4.0", true),
+        release("v1.10.0", false),
+    ];
+    assert_eq!(latest_stable(&metals), Some("v1.10.0"));
+
+    let temurin = [
+        release("jdk-21.0.3+7-ea-beta", true),
+        release("jdk-21.0.2+13", false),
+        release("jdk-21.0.2+12", false),
+        release("jdk-21+35", false),
+        release("jdk-22+36-ea-beta", false),
+    ];
+    assert_eq!(latest_stable(&temurin), Some("jdk-21.0.2+13"));
+
+    assert_eq!(latest_stable(&[release("v2.0.0", true)]), None);
+    assert_eq!(release_version("1.3.0"), Version::parse("1.3.0"));
+    assert_eq!(release_version("v1.0.0-RC1"), None);
+}
+
+// GitHub's own /releases/latest skips pre-releases but happily returns a
+// nightly or an EA build tagged as a release, so the whole first page gets
+// sorted through instead. The version is returned without a `v` prefix
+fn query(api: &str, repo: &str, token: Option<&str>) -> Result<String> {
+    let url = format!("{api}repos/{repo}/releases?per_page=100");
+    let mut headers = vec![
+        ("accept", "application/vnd.github+json".to_string()),
+        ("user-agent", "scala-lapce".to_string()),
+    ];
+    if let Some(token) = token {
+        headers.push(("authorization", format!("Bearer {token}")));
+    }
+    let response = download::get_text(&url, &headers, &RATE_LIMIT_HEADERS)?;
+    if let Some(limit) = rate_limit(&response, now(), token.is_some()) {
+        return Err(limit.into());
+    }
+    if !response.status.is_success() {
+        return Err(Rejected {
+            url,
+            status: response.status,
+        }
+        .into());
+    }
+    let releases: Vec<Release> = serde_json::from_str(&response.body)
+        .with_context(|| format!("unexpected response from {url}"))?;
+    let latest = latest_stable(&releases).ok_or_else(|| anyhow!("{repo} has no stable release"))?;
+    Ok(latest.trim_start_matches('v').to_string())
+}
+
+fn latest_release(repo: &str, check: &ReleaseCheck, cache: &Path, now: u64) -> Result<String> {
+    resolve_release(repo, check, cache, now, |token| {
+        query(check.api(), repo, token)
+    })
+}
+
+// a rate limit that resets within MAX_RATE_LIMIT_WAIT and the deadline
+fn rate_limit_wait(e: &anyhow::Error, deadline: Instant) -> Option<Duration> {
+    let wait = e.downcast_ref::<RateLimited>()?.wait?;
+    (wait <= MAX_RATE_LIMIT_WAIT && Instant::now() + wait < deadline).then_some(wait)
+}
+
+// the release from the fresh cache, else from `query`, else whatever the
+// cache had last
+fn resolve_release(
+    repo: &str,
+    check: &ReleaseCheck,
+    cache: &Path,
+    now: u64,
+    mut query: impl FnMut(Option<&str>) -> Result<String>,
+) -> Result<String> {
+    let mut entries = read_cache(cache);
+    if let Some(entry) = entries
+        .get(repo)
+        .filter(|entry| entry.is_fresh(now, check.ttl))
+    {
+        return Ok(entry.version.clone());
+    }
+
+    let mut token = check.token.as_deref();
+    let deadline = Instant::now() + check.timeout;
+    let result = retry(ATTEMPTS, INITIAL_BACKOFF, deadline, || {
+        match query(token) {
+            // a revoked or mistyped token shouldn't be worse than none at all
+            Err(e) if token.is_some() && is_unauthorized(&e) => {
+                PLUGIN_RPC.window_show_message(
+                    MessageType::WARNING,
+                    "GitHub rejected the configured token, checking releases unauthenticated"
+                        .to_string(),
+                );
+                token = None;
+                query(None)
+            }
+            Err(e) => match rate_limit_wait(&e, deadline) {
+                Some(wait) => {
+                    logging::info(format!("{e}, waiting {} seconds", wait.as_secs()));
+                    sleep(wait);
+                    query(token)
+                }
+                None => Err(e),
+            },
+            result => result,
+        }
+    });
+    match result {
+        Ok(version) => {
+            if let Err(e) = remember(cache, repo, &version, now) {
+                logging::debug(format!("couldn't cache the {repo} release: {e:#}"));
+            }
+            Ok(version)
+        }
+        // a stale release still beats failing to start
+        Err(e) => match entries.remove(repo).map(|entry| entry.version) {
+            Some(version) => {
+                PLUGIN_RPC.window_show_message(
+                    MessageType::WARNING,
+                    format!("{e:#}, using the last known release {version}"),
+                );
+                Ok(version)
+            }
+            None => Err(e.context(format!("failed to query latest release for {repo}"))),
+        },
+    }
+}
+
+pub fn get_latest_release_for(repo: &str, check: &ReleaseCheck) -> Result<String> {
+    latest_release(repo, check, Path::new(CACHE_FILE), now())
+}
+
+#[test]
+fn test_latest_release_uses_fresh_cache() {
+    let path = std::env::temp_dir().join("scala-lapce-test-fresh-release-cache/releases.json");
+    let _ = fs::remove_file(&path);
+    // not a real repo, so this only passes without going to GitHub
+    let repo = "scala-lapce/does-not-exist";
+    remember(&path, repo, "v0.1.0", 1_000).unwrap();
+    let check = ReleaseCheck {
+        token: None,
+        ttl: Duration::from_secs(60),
+        timeout: Duration::ZERO,
+        api_url: None,
+    };
+    assert_eq!(
+        latest_release(repo, &check, &path, 1_030).unwrap(),
+        "v0.1.0"
+    );
+}
+
+#[test]
+fn test_latest_release_network_error() {
+    let path = std::env::temp_dir().join("scala-lapce-test-release-network-error/releases.json");
+    let _ = fs::remove_file(&path);
+    let check = ReleaseCheck {
+        timeout: Duration::from_secs(60),
+        ..ReleaseCheck::default()
+    };
+    let mut calls = 0;
+    let e = resolve_release("scalameta/metals", &check, &path, 1_000, |_| {
+        calls += 1;
+        Err(anyhow!(
+            "GET https://api.github.com/repos/scalameta/metals/releases failed: connection refused"
+        ))
+    })
+    .unwrap_err();
+    assert_eq!(calls, ATTEMPTS);
+    assert_eq!(
+        e.to_string(),
+        "failed to query latest release for scalameta/metals"
+    );
+    assert!(format!("{e:#}").contains("connection refused"));
+}
+
+#[test]
+fn test_latest_release_when_rate_limited() {
+    let path = std::env::temp_dir().join("scala-lapce-test-rate-limited-release/releases.json");
+    let _ = fs::remove_file(&path);
+    let repo = "scalameta/metals";
+    let check = ReleaseCheck {
+        timeout: Duration::from_secs(60),
+        ..ReleaseCheck::default()
+    };
+    let limited = |wait: u64| {
+        anyhow::Error::from(RateLimited {
+            wait: Some(Duration::from_secs(wait)),
+            authenticated: false,
+        })
+    };
+
+    // nothing cached, the user gets told why and what to do about it
+    let mut calls = 0;
+    let e = resolve_release(repo, &check, &path, 1_000, |_| {
+        calls += 1;
+        Err(limited(3600))
+    })
+    .unwrap_err();
+    assert_eq!(calls, 1);
+    assert!(format!("{e:#}").contains("resets in 60 minutes"));
+    assert!(format!("{e:#}").contains("GITHUB_TOKEN"));
+
+    // a limit about to reset is waited out
+    let mut calls = 0;
+    let version = resolve_release(repo, &check, &path, 1_000, |_| {
+        calls += 1;
+        match calls {
+            1 => Err(limited(0)),
+            _ => Ok("1.3.1".to_string()),
+        }
+    });
+    assert_eq!(version.unwrap(), "1.3.1");
+    assert_eq!(calls, 2);
+
+    // otherwise the last known release is used
+    let version = resolve_release(repo, &check, &path, 1_000 + 48 * 60 * 60, |_| {
+        Err(limited(3600))
+    });
+    assert_eq!(version.unwrap(), "1.3.1");
+}
+
+#[test]
+fn test_get_latest_release_for() {
+    // luckily for us the crate for this hasn't been updated in >1yr since commiting this
+    // so is a good test object
+    let repo = "celeo/github_release_check";
+    // what initialize uses without any settings
+    let check = ReleaseCheck {
+        ttl: Duration::from_secs(24 * 60 * 60),
+        timeout: download::DEFAULT_TIMEOUT,
+        ..ReleaseCheck::default()
+    };
+    let latest_version = get_latest_release_for(repo, &check).unwrap();
+    assert_eq!(latest_version, "0.2.1");
+}
@@ -0,0 +1,128 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::scala::VersionConfig;
+use crate::PluginInfo;
+
+// detected versions for each workspace, relative to the plugin working directory
+pub const CACHE_FILE: &str = "cache/plugin-info.json";
+
+// what's on PATH can change without any file in the workspace changing
+const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+// the workspace files the versions are read from, detection runs again
+// when any of them is added, removed or modified
+const VERSION_FILES: [&str; 12] = [
+    "build.sbt",
+    "project/build.properties",
+    "build.sc",
+    "build.mill",
+    ".mill-version",
+    ".config/mill-version",
+    "build.gradle",
+    "build.gradle.kts",
+    "pom.xml",
+    "project.scala",
+    ".scala-version",
+    ".tool-versions",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    // modification time of each version file there is, in seconds
+    fingerprint: BTreeMap<String, u64>,
+    version_config: VersionConfig,
+    detected_at: u64,
+    info: PluginInfo,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+fn fingerprint(root: &Path) -> BTreeMap<String, u64> {
+    VERSION_FILES
+        .iter()
+        .filter_map(|file| {
+            let modified = fs::metadata(root.join(file)).ok()?.modified().ok()?;
+            let modified = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+            Some((file.to_string(), modified))
+        })
+        .collect()
+}
+
+fn read_cache(path: &Path) -> BTreeMap<String, Entry> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|cache| serde_json::from_str(&cache).ok())
+        .unwrap_or_default()
+}
+
+// the versions detected last time, unless the workspace or the settings
+// they were detected with have changed since
+pub fn load(
+    path: &Path,
+    root: &Path,
+    version_config: &VersionConfig,
+    now: u64,
+) -> Option<PluginInfo> {
+    let entry = read_cache(path).remove(&root.display().to_string())?;
+    let fresh = now.saturating_sub(entry.detected_at) < MAX_AGE.as_secs();
+    (fresh && entry.version_config == *version_config && entry.fingerprint == fingerprint(root))
+        .then_some(entry.info)
+}
+
+pub fn store(
+    path: &Path,
+    root: &Path,
+    version_config: &VersionConfig,
+    info: &PluginInfo,
+) -> Result<()> {
+    let mut cache = read_cache(path);
+    cache.insert(
+        root.display().to_string(),
+        Entry {
+            fingerprint: fingerprint(root),
+            version_config: version_config.clone(),
+            detected_at: now(),
+            info: info.clone(),
+        },
+    );
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&cache)?)?;
+    Ok(())
+}
+
+#[test]
+fn test_info_cache() {
+    let dir = std::env::temp_dir().join("scala-lapce-test-info-cache");
+    let _ = fs::remove_dir_all(&dir);
+    let root = dir.join("shop");
+    fs::create_dir_all(root.join("project")).unwrap();
+    fs::write(root.join("project/build.properties"), "sbt.version=1.9.9\n").unwrap();
+    let path = dir.join("cache/plugin-info.json");
+    let config = VersionConfig::default();
+    let info = PluginInfo {
+        scala_version: Some("3.3.1".to_string()),
+        ..PluginInfo::default()
+    };
+    assert_eq!(load(&path, &root, &config, now()), None);
+
+    store(&path, &root, &config, &info).unwrap();
+    assert_eq!(load(&path, &root, &config, now()), Some(info.clone()));
+    assert_eq!(load(&path, &root, &config, now() + MAX_AGE.as_secs()), None);
+    assert_eq!(load(&path, &dir, &config, now()), None);
+
+    // a new build file means the versions may have changed
+    fs::write(root.join(".scala-version"), "3.4.0\n").unwrap();
+    assert_eq!(load(&path, &root, &config, now()), None);
+}
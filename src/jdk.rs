@@ -0,0 +1,1323 @@
+use anyhow::{anyhow, Context, Result};
+use lapce_plugin::{psp_types::lsp_types::Url, VoltEnvironment};
+use regex::Regex::new as Regexp;
+use serde::Deserialize;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::launch::{host_path, plugin_uri};
+#[cfg(test)]
+use crate::runner::MockRunner;
+use crate::runner::{CommandOutput, CommandRunner, SystemRunner};
+use crate::version::Version;
+use crate::{adoptium, download, extract, logging, read_major_jdk_version};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    Linux,
+    Mac,
+    Windows,
+}
+
+impl Os {
+    pub fn from_volt(os: &str) -> Option<Os> {
+        match os {
+            "linux" => Some(Os::Linux),
+            "macos" => Some(Os::Mac),
+            "windows" => Some(Os::Windows),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Os::Linux => "linux",
+            Os::Mac => "mac",
+            Os::Windows => "windows",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X64,
+    Aarch64,
+}
+
+impl Arch {
+    // every vendor we support spells these the same way
+    pub fn name(self) -> &'static str {
+        match self {
+            Arch::X64 => "x64",
+            Arch::Aarch64 => "aarch64",
+        }
+    }
+}
+
+// only matters on Linux, where glibc builds of the JDK crash on musl
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Libc {
+    #[default]
+    Gnu,
+    Musl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Platform {
+    pub os: Os,
+    pub arch: Arch,
+    pub libc: Libc,
+}
+
+impl Platform {
+    pub fn detect() -> Result<Platform> {
+        let os = VoltEnvironment::operating_system()?;
+        let arch = VoltEnvironment::architecture()?;
+        Platform::from_volt(&os, &arch)
+    }
+
+    // Volt reports std::env::consts::{OS, ARCH} of the host running Lapce
+    pub fn from_volt(os: &str, arch: &str) -> Result<Platform> {
+        let unsupported = || anyhow!("no managed JDK available for {os}/{arch}");
+        let os = Os::from_volt(os).ok_or_else(unsupported)?;
+        let arch = match arch {
+            "x86_64" | "x64" | "amd64" => Arch::X64,
+            "aarch64" | "arm64" => Arch::Aarch64,
+            _ => return Err(unsupported()),
+        };
+        Ok(Platform {
+            os,
+            arch,
+            libc: Libc::Gnu,
+        })
+    }
+}
+
+// Volt reports the libc Lapce was built against, which needn't be the one
+// the system has (e.g. a glibc Lapce running on Alpine through gcompat), so
+// ask ldd as well. musl's ldd prints `musl libc (x86_64)` to stderr
+pub fn detect_libc(volt_libc: Option<&str>, runner: &dyn CommandRunner) -> Libc {
+    if volt_libc == Some("musl") {
+        return Libc::Musl;
+    }
+    match runner.run("ldd", &["--version"]) {
+        Ok(output) if format!("{}{}", output.stdout, output.stderr).contains("musl") => Libc::Musl,
+        _ => Libc::Gnu,
+    }
+}
+
+#[test]
+fn test_detect_libc() {
+    let alpine = MockRunner::default().with(
+        "ldd --version",
+        false,
+        "",
+        "musl libc (x86_64)\nVersion 1.2.4\nDynamic Program Loader\n",
+    );
+    assert_eq!(detect_libc(Some("gnu"), &alpine), Libc::Musl);
+    let debian = MockRunner::default().with(
+        "ldd --version",
+        true,
+        "ldd (Debian GLIBC 2.36-9+deb12u4) 2.36\n",
+        "",
+    );
+    assert_eq!(detect_libc(Some("gnu"), &debian), Libc::Gnu);
+    assert_eq!(detect_libc(Some("musl"), &debian), Libc::Musl);
+    assert_eq!(detect_libc(None, &MockRunner::default()), Libc::Gnu);
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JdkVendor {
+    #[default]
+    Temurin,
+    Zulu,
+    Corretto,
+    GraalVm,
+}
+
+impl JdkVendor {
+    pub fn name(self) -> &'static str {
+        match self {
+            JdkVendor::Temurin => "temurin",
+            JdkVendor::Zulu => "zulu",
+            JdkVendor::Corretto => "corretto",
+            JdkVendor::GraalVm => "graalvm",
+        }
+    }
+
+    // GitHub repo the vendor publishes releases to, Azul only has its CDN
+    pub fn release_repo(self, version: JdkVersion) -> Option<String> {
+        match self {
+            JdkVendor::Temurin => Some(format!("adoptium/temurin{version}-binaries")),
+            JdkVendor::Zulu => None,
+            JdkVendor::Corretto => Some(format!("corretto/corretto-{version}")),
+            JdkVendor::GraalVm => Some("graalvm/graalvm-ce-builds".to_string()),
+        }
+    }
+
+    // pinned, for when the release lookup fails or finds nothing usable
+    pub fn release(self, version: JdkVersion) -> Option<&'static str> {
+        let release = match (self, version.0) {
+            (JdkVendor::Temurin, 11) => "11.0.22+7",
+            (JdkVendor::Temurin, 17) => "17.0.10+7",
+            (JdkVendor::Temurin, 21) => "21.0.2+13",
+            (JdkVendor::Zulu, 11) => "11.70.15-ca-jdk11.0.22",
+            (JdkVendor::Zulu, 17) => "17.48.15-ca-jdk17.0.10",
+            (JdkVendor::Zulu, 21) => "21.32.17-ca-jdk21.0.2",
+            (JdkVendor::Corretto, 11) => "11.0.22.7.1",
+            (JdkVendor::Corretto, 17) => "17.0.10.7.1",
+            (JdkVendor::Corretto, 21) => "21.0.2.13.1",
+            // GraalVM community editions start at 17
+            (JdkVendor::GraalVm, 17) => "17.0.9",
+            (JdkVendor::GraalVm, 21) => "21.0.2",
+            _ => return None,
+        };
+        Some(release)
+    }
+
+    // the release in the form `release` and `asset` use, from the tag
+    // get_latest_release_for found: Temurin and GraalVM tag theirs
+    // `jdk-21.0.2+13` and `jdk-21.0.2`, Corretto uses the bare `21.0.2.13.1`.
+    // GraalVM keeps every feature release in one repo, so a tag of another
+    // one than configured is rejected
+    pub fn release_from_tag(self, version: JdkVersion, tag: &str) -> Option<String> {
+        let release = tag
+            .trim()
+            .trim_start_matches('v')
+            .trim_start_matches("jdk-");
+        (release_major(release)? == version.0).then(|| release.to_string())
+    }
+
+    pub fn asset(self, platform: Platform, package: JdkPackage, release: &str) -> Result<JdkAsset> {
+        let Platform { os, arch, libc } = platform;
+        let url = build_jdk_url(self, package, release, os, arch, libc)?;
+        let filename = url.rsplit('/').next().unwrap_or_default().to_string();
+        let checksum_url = match self {
+            JdkVendor::Temurin => Some(format!("{url}.sha256.txt")),
+            JdkVendor::GraalVm => Some(format!("{url}.sha256")),
+            JdkVendor::Zulu | JdkVendor::Corretto => None,
+        };
+        Ok(JdkAsset {
+            url,
+            filename,
+            checksum_url,
+            checksum: None,
+        })
+    }
+}
+
+// feature release a vendor release belongs to, e.g. 21 for 21.0.2+13
+fn release_major(release: &str) -> Option<u32> {
+    release.split(['.', '+', '-']).next()?.parse().ok()
+}
+
+// download URL of the JDK or JRE archive for `version`, which is a release
+// as the vendor numbers it (see JdkVendor::release)
+pub fn build_jdk_url(
+    vendor: JdkVendor,
+    package: JdkPackage,
+    version: &str,
+    os: Os,
+    arch: Arch,
+    libc: Libc,
+) -> Result<String> {
+    if os == Os::Windows && arch == Arch::Aarch64 && vendor != JdkVendor::Zulu {
+        return Err(anyhow!(
+            "{} doesn't ship Windows on ARM builds, try jdkVendor = \"zulu\"",
+            vendor.name()
+        ));
+    }
+    // Adoptium builds a JRE for every platform it builds a JDK for, the
+    // other vendors' JREs are older releases or none at all
+    if package == JdkPackage::Jre && vendor != JdkVendor::Temurin {
+        return Err(anyhow!(
+            "{} doesn't ship JRE builds, try jdkVendor = \"temurin\" or jdkPackage = \"jdk\"",
+            vendor.name()
+        ));
+    }
+    let musl = os == Os::Linux && libc == Libc::Musl;
+    if musl && vendor == JdkVendor::GraalVm {
+        return Err(anyhow!(
+            "graalvm doesn't ship musl builds, try jdkVendor = \"temurin\" or install a JDK with your package manager"
+        ));
+    }
+    let major = release_major(version)
+        .ok_or_else(|| anyhow!("unrecognized {} release {version:?}", vendor.name()))?;
+    let repo = vendor.release_repo(JdkVersion(major)).unwrap_or_default();
+    let arch = arch.name();
+    // the zip rather than Temurin's msi, an installer can't be unpacked
+    // and there's no running msiexec from the sandbox
+    let ext = match os {
+        Os::Windows => "zip",
+        _ => "tar.gz",
+    };
+
+    let url = match vendor {
+        // OpenJDK21U-jdk_aarch64_linux_hotspot_21.0.2_13.tar.gz, or jre_
+        JdkVendor::Temurin => {
+            let os = match os {
+                Os::Linux if musl => "alpine-linux",
+                Os::Linux => "linux",
+                Os::Mac => "mac",
+                Os::Windows => "windows",
+            };
+            format!(
+                "https://github.com/{repo}/releases/download/jdk-{}/{}-{}_{arch}_{os}_hotspot_{}.{ext}",
+                version.replace('+', "%2B"),
+                read_major_jdk_version(version)?,
+                package.name(),
+                version.replace('+', "_"),
+            )
+        }
+        // zulu21.32.17-ca-jdk21.0.2-macosx_aarch64.tar.gz
+        JdkVendor::Zulu => {
+            let os = match os {
+                Os::Linux if musl => "linux_musl",
+                Os::Linux => "linux",
+                Os::Mac => "macosx",
+                Os::Windows => "win",
+            };
+            format!("https://cdn.azul.com/zulu/bin/zulu{version}-{os}_{arch}.{ext}")
+        }
+        // amazon-corretto-21.0.2.13.1-linux-aarch64.tar.gz
+        JdkVendor::Corretto => {
+            let filename = match os {
+                Os::Linux if musl => format!("amazon-corretto-{version}-alpine-linux-{arch}.{ext}"),
+                Os::Linux => format!("amazon-corretto-{version}-linux-{arch}.{ext}"),
+                Os::Mac => format!("amazon-corretto-{version}-macosx-{arch}.{ext}"),
+                Os::Windows => format!("amazon-corretto-{version}-windows-{arch}-jdk.{ext}"),
+            };
+            format!("https://corretto.aws/downloads/resources/{version}/{filename}")
+        }
+        // graalvm-community-jdk-21.0.2_linux-aarch64_bin.tar.gz
+        JdkVendor::GraalVm => {
+            let os = match os {
+                Os::Linux => "linux",
+                Os::Mac => "macos",
+                Os::Windows => "windows",
+            };
+            format!(
+                "https://github.com/{repo}/releases/download/jdk-{version}/graalvm-community-jdk-{version}_{os}-{arch}_bin.{ext}"
+            )
+        }
+    };
+    Ok(url)
+}
+
+// what gets downloaded. A JRE is enough to run Metals but has no javac,
+// so only suits builds that compile in Bloop or sbt on a JDK of their own
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JdkPackage {
+    #[default]
+    Jdk,
+    Jre,
+}
+
+impl JdkPackage {
+    // Adoptium's image type
+    pub fn name(self) -> &'static str {
+        match self {
+            JdkPackage::Jdk => "jdk",
+            JdkPackage::Jre => "jre",
+        }
+    }
+}
+
+// a downloadable JDK archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JdkAsset {
+    pub url: String,
+    pub filename: String,
+    pub checksum_url: Option<String>,
+    // sha256 when the vendor's API hands it out directly
+    pub checksum: Option<String>,
+}
+
+// LTS releases Metals runs on
+pub const SUPPORTED_JDK_VERSIONS: [u32; 3] = [11, 17, 21];
+
+// JDK feature release, 21 unless configured otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct JdkVersion(u32);
+
+impl Default for JdkVersion {
+    fn default() -> Self {
+        JdkVersion(21)
+    }
+}
+
+impl TryFrom<String> for JdkVersion {
+    type Error = String;
+
+    fn try_from(version: String) -> Result<Self, Self::Error> {
+        match version.trim().parse() {
+            Ok(major) if SUPPORTED_JDK_VERSIONS.contains(&major) => Ok(JdkVersion(major)),
+            _ => Err(format!(
+                "unsupported jdkVersion \"{version}\", Metals supports JDK {}",
+                SUPPORTED_JDK_VERSIONS.map(|v| v.to_string()).join(", ")
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for JdkVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JdkConfig {
+    #[serde(default)]
+    pub jdk_vendor: JdkVendor,
+    #[serde(default)]
+    pub jdk_version: JdkVersion,
+    #[serde(default)]
+    pub jdk_package: JdkPackage,
+    // throw away the cached JDK and download it again
+    #[serde(default)]
+    pub reinstall_jdk: bool,
+    // never download a JDK, for JDKs managed with SDKMAN, nix and the like
+    #[serde(default)]
+    pub disable_auto_jdk_download: bool,
+    // an installed JDK to use as is, skipping detection and downloads
+    #[serde(default)]
+    pub java_home: Option<String>,
+    // a mirror of GitHub's release downloads, e.g. an Artifactory remote
+    #[serde(default)]
+    pub jdk_mirror_url: Option<String>,
+}
+
+impl JdkConfig {
+    pub fn from_options(options: Option<&Value>) -> Result<JdkConfig> {
+        match options {
+            Some(options) => {
+                serde_json::from_value(options.clone()).context("invalid JDK configuration")
+            }
+            None => Ok(JdkConfig::default()),
+        }
+    }
+
+    pub fn configured_java_home(&self) -> Option<&str> {
+        self.java_home
+            .as_deref()
+            .map(str::trim)
+            .filter(|home| !home.is_empty())
+    }
+
+    // the mirror to download from, None when unset or not a URL, so a
+    // typo in it doesn't stop Metals from starting
+    pub fn mirror(&self) -> Option<Url> {
+        let mirror = self
+            .jdk_mirror_url
+            .as_deref()
+            .map(str::trim)
+            .filter(|mirror| !mirror.is_empty())?;
+        Url::parse(mirror)
+            .map_err(|e| {
+                logging::warn(format!(
+                    "jdkMirrorUrl {mirror:?} isn't a URL ({e}), downloading from the vendor"
+                ))
+            })
+            .ok()
+    }
+}
+
+// `url` with its scheme and host replaced by the mirror's, the mirror's own
+// path going in front of the original one
+pub fn with_mirror(url: &str, mirror: &Url) -> Result<String> {
+    let url = Url::parse(url)?;
+    let mut mirrored = format!("{}{}", mirror.as_str().trim_end_matches('/'), url.path());
+    if let Some(query) = url.query() {
+        mirrored = format!("{mirrored}?{query}");
+    }
+    Ok(mirrored)
+}
+
+#[test]
+fn test_with_mirror() {
+    let url = "https://github.com/adoptium/temurin21-binaries/releases/download/jdk-21.0.2%2B13/OpenJDK21U-jdk_x64_linux_hotspot_21.0.2_13.tar.gz";
+    let mirror = Url::parse("https://artifactory.example.com/github/").unwrap();
+    assert_eq!(
+        with_mirror(url, &mirror).unwrap(),
+        "https://artifactory.example.com/github/adoptium/temurin21-binaries/releases/download/jdk-21.0.2%2B13/OpenJDK21U-jdk_x64_linux_hotspot_21.0.2_13.tar.gz"
+    );
+    let mirror = Url::parse("http://10.0.0.5:8081").unwrap();
+    assert_eq!(
+        with_mirror("https://cdn.azul.com/zulu/bin/zulu21.tar.gz", &mirror).unwrap(),
+        "http://10.0.0.5:8081/zulu/bin/zulu21.tar.gz"
+    );
+
+    let mirror = |value: &str| {
+        JdkConfig::from_options(Some(&serde_json::json!({ "jdkMirrorUrl": value })))
+            .unwrap()
+            .mirror()
+    };
+    assert_eq!(
+        mirror("https://artifactory.example.com/github").map(String::from),
+        Some("https://artifactory.example.com/github".to_string())
+    );
+    assert_eq!(mirror(""), None);
+}
+
+pub fn check_java_home(home: &str) -> Result<()> {
+    if !has_java(Path::new(home)) {
+        return Err(anyhow!("javaHome {home} doesn't contain bin/java"));
+    }
+    Ok(())
+}
+
+// a JAVA_HOME the user already set up, as long as it actually holds a JDK
+pub fn system_java_home() -> Option<String> {
+    let home = env::var("JAVA_HOME").ok().filter(|home| !home.is_empty())?;
+    has_java(Path::new(&home)).then_some(home)
+}
+
+fn has_java(home: &Path) -> bool {
+    let bin = home.join("bin");
+    bin.join("java").exists() || bin.join("java.exe").exists()
+}
+
+// archives differ in how deep the JDK sits (`<dir>/bin`, macOS bundles under
+// `Contents/Home`, Zulu nesting the bundle once more), so look for it
+fn find_java_home(dir: &Path, depth: usize) -> Option<PathBuf> {
+    if has_java(dir) {
+        return Some(dir.to_path_buf());
+    }
+    if depth == 0 {
+        return None;
+    }
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .find_map(|path| find_java_home(&path, depth - 1))
+}
+
+// version from the output of either `java --version`, which prints
+// `openjdk 21.0.2 2024-01-16 LTS` (or `java 21.0.2 ...` for Oracle builds),
+// or the older `java -version` with `openjdk version "21.0.2" 2024-01-16`
+// and the legacy `java version "1.8.0_381"`
+pub fn parse_java_version(output: &str) -> Option<String> {
+    let re = Regexp(r#"(?m)^(?:openjdk|java)(?: version)? "?(\d+(?:\.\d+)*(?:_\d+)?(?:-ea)?)"?"#)
+        .unwrap();
+    re.captures(output).map(|captures| captures[1].to_string())
+}
+
+#[test]
+fn test_parse_java_version() {
+    let temurin = "\
+openjdk 21.0.2 2024-01-16 LTS
+OpenJDK Runtime Environment Temurin-21.0.2+13 (build 21.0.2+13-LTS)
+OpenJDK 64-Bit Server VM Temurin-21.0.2+13 (build 21.0.2+13-LTS, mixed mode, sharing)
+";
+    assert_eq!(parse_java_version(temurin).as_deref(), Some("21.0.2"));
+
+    let graalvm = "\
+openjdk 21.0.2 2024-01-16
+OpenJDK Runtime Environment GraalVM CE 21.0.2+13.1 (build 21.0.2+13-jvmci-23.1-b30)
+OpenJDK 64-Bit Server VM GraalVM CE 21.0.2+13.1 (build 21.0.2+13-jvmci-23.1-b30, mixed mode, sharing)
+";
+    assert_eq!(parse_java_version(graalvm).as_deref(), Some("21.0.2"));
+
+    let zulu = "\
+openjdk version \"17.0.10\" 2024-01-16 LTS
+OpenJDK Runtime Environment Zulu17.48+15-CA (build 17.0.10+7-LTS)
+OpenJDK 64-Bit Server VM Zulu17.48+15-CA (build 17.0.10+7-LTS, mixed mode, sharing)
+";
+    assert_eq!(parse_java_version(zulu).as_deref(), Some("17.0.10"));
+
+    // a GA release without update number, and Oracle's own builds
+    assert_eq!(
+        parse_java_version("openjdk 21 2023-09-19\n").as_deref(),
+        Some("21")
+    );
+    assert_eq!(
+        parse_java_version("java 21.0.2 2024-01-16 LTS\n").as_deref(),
+        Some("21.0.2")
+    );
+    assert_eq!(
+        parse_java_version("Picked up JAVA_TOOL_OPTIONS: -Xmx1g\njava version \"1.8.0_381\"\n")
+            .as_deref(),
+        Some("1.8.0_381")
+    );
+    assert_eq!(parse_java_version("Unrecognized option: --version\n"), None);
+}
+
+// `21.0.2`, `22-ea`, or the legacy `1.8.0_381` numbered as 8.0.381.
+// Vendors' fourth number, as in `11.0.20.1`, is left out
+pub fn java_version(version: &str) -> Option<Version> {
+    let version = match version.strip_prefix("1.") {
+        Some(legacy) => legacy.replacen('_', ".", 1),
+        None => version.to_string(),
+    };
+    Version::parse(&version.split('.').take(3).collect::<Vec<_>>().join("."))
+}
+
+pub fn java_major_version(version: &str) -> Option<u32> {
+    java_version(version).map(|version| version.major)
+}
+
+// version of the java on PATH, None if there is none. `--version` prints
+// to stdout but only exists since JDK 9, `-version` prints to stderr
+pub fn path_java_version(runner: &dyn CommandRunner) -> Option<String> {
+    let version = |flag: &str| {
+        let output = runner
+            .run("java", &[flag])
+            .map_err(|e| logging::debug(format!("java {flag}: {e}")))
+            .ok()?;
+        if !output.success {
+            return None;
+        }
+        parse_java_version(&output.stdout).or_else(|| parse_java_version(&output.stderr))
+    };
+    version("--version").or_else(|| version("-version"))
+}
+
+#[test]
+fn test_path_java_version() {
+    // JDK 8 doesn't know --version
+    let runner = MockRunner::default()
+        .with(
+            "java --version",
+            false,
+            "",
+            "Unrecognized option: --version\n",
+        )
+        .with("java -version", true, "", "java version \"1.8.0_381\"\n");
+    assert_eq!(path_java_version(&runner).as_deref(), Some("1.8.0_381"));
+
+    let runner = MockRunner::default().with(
+        "java --version",
+        true,
+        "openjdk 21.0.2 2024-01-16 LTS\n",
+        "",
+    );
+    assert_eq!(path_java_version(&runner).as_deref(), Some("21.0.2"));
+
+    assert_eq!(path_java_version(&MockRunner::default()), None);
+}
+
+// whether `version` is `minimum` or newer, None when either isn't a version
+pub fn java_at_least(version: &str, minimum: &str) -> Option<bool> {
+    Some(java_version(version)? >= java_version(minimum)?)
+}
+
+// whether a java of this version can run Metals
+pub fn is_supported_java(version: &str) -> bool {
+    java_major_version(version).is_some_and(|major| major >= SUPPORTED_JDK_VERSIONS[0])
+}
+
+// the version of the JDK in `home`, None when its java doesn't run
+pub fn home_java_version(home: &Path, runner: &dyn CommandRunner) -> Option<String> {
+    verify_java(&java_binary(home)?, runner)
+        .map_err(|e| logging::debug(format!("{e:#}")))
+        .ok()
+}
+
+#[test]
+fn test_java_at_least() {
+    assert_eq!(java_at_least("11.0.22", "11"), Some(true));
+    assert_eq!(java_at_least("17.0.1", "17.0.10"), Some(false));
+    assert_eq!(java_at_least("1.8.0_381", "1.8.0_100"), Some(true));
+    assert_eq!(java_at_least("1.8.0_381", "11"), Some(false));
+    assert_eq!(java_at_least("21", "unknown"), None);
+
+    // what a JDK 8 prints for `java -version`, on stderr
+    let jdk8 = "\
+java version \"1.8.0_381\"
+Java(TM) SE Runtime Environment (build 1.8.0_381-b09)
+Java HotSpot(TM) 64-Bit Server VM (build 25.381-b09, mixed mode)
+";
+    let home = std::env::temp_dir().join("scala-lapce-test-home-java-version");
+    let _ = fs::remove_dir_all(&home);
+    fs::create_dir_all(home.join("bin")).unwrap();
+    assert_eq!(home_java_version(&home, &MockRunner::default()), None);
+    fs::write(home.join("bin/java"), b"").unwrap();
+    let command = format!("{} -version", home.join("bin/java").display());
+    let runner = MockRunner::default().with(&command, true, "", jdk8);
+    let version = home_java_version(&home, &runner).unwrap();
+    assert_eq!(version, "1.8.0_381");
+    assert!(!is_supported_java(&version));
+}
+
+fn java_binary(home: &Path) -> Option<PathBuf> {
+    let bin = home.join("bin");
+    [bin.join("java"), bin.join("java.exe")]
+        .into_iter()
+        .find(|java| java.exists())
+}
+
+// run `java -version` to make sure a JDK isn't half-extracted, built for
+// another architecture or missing libraries, and return the version it reports
+pub fn verify_java(java: &Path, runner: &dyn CommandRunner) -> Result<String> {
+    let program = java.to_string_lossy();
+    let output = runner
+        .run(&program, &["-version"])
+        .with_context(|| format!("cannot run {program}"))?;
+    check_java_output(&program, &output)
+}
+
+fn check_java_output(program: &str, output: &CommandOutput) -> Result<String> {
+    let reported = format!("{}{}", output.stderr, output.stdout);
+    let first_line = reported.lines().next().unwrap_or_default();
+    if !output.success {
+        return Err(anyhow!("{program} -version failed: {first_line}"));
+    }
+    parse_java_version(&reported)
+        .ok_or_else(|| anyhow!("{program} -version printed no version: {first_line}"))
+}
+
+// like verify_java for a JDK we downloaded, `java` being its host path.
+// Ok(None) when the host couldn't start it at all, that says nothing about
+// the JDK, only a java that ran and failed is an Err
+fn verify_installed_java(java: &str, runner: &dyn CommandRunner) -> Result<Option<String>> {
+    match runner.run(java, &["-version"]) {
+        Ok(output) => check_java_output(java, &output).map(Some),
+        Err(e) => {
+            logging::warn(format!("couldn't run {java} to check the JDK: {e}"));
+            Ok(None)
+        }
+    }
+}
+
+// a java of ours as the host sees it, the host runs it rather than us
+fn host_java(java: &Path, os: Os) -> Result<String> {
+    let volt_uri = Url::parse(&VoltEnvironment::uri()?)?;
+    host_path(&plugin_uri(&volt_uri, &java.to_string_lossy())?, os)
+}
+
+// verify_installed_java on the host
+fn check_installed_java(java: &Path, os: Os) -> Result<Option<String>> {
+    match host_java(java, os) {
+        Ok(java) => verify_installed_java(&java, &SystemRunner),
+        Err(e) => {
+            logging::warn(format!("can't check {}: {e:#}", java.display()));
+            Ok(None)
+        }
+    }
+}
+
+#[test]
+fn test_verify_java() {
+    let java = Path::new("jdk/temurin-21.0.2+13-linux-x64/jdk-21.0.2+13/bin/java");
+    let command = format!("{} -version", java.display());
+    let runner = MockRunner::default().with(
+        &command,
+        true,
+        "",
+        "openjdk version \"21.0.2\" 2024-01-16 LTS\nOpenJDK Runtime Environment Temurin-21.0.2+13 (build 21.0.2+13-LTS)\n",
+    );
+    assert_eq!(verify_java(java, &runner).unwrap(), "21.0.2");
+
+    // a JDK for another architecture extracts fine but can't execute
+    let runner = MockRunner::default().with(
+        &command,
+        false,
+        "",
+        "cannot execute binary file: Exec format error\n",
+    );
+    let err = verify_java(java, &runner).unwrap_err();
+    assert!(format!("{err:#}").contains("Exec format error"));
+
+    let runner = MockRunner::default().with(&command, true, "", "Segmentation fault\n");
+    assert!(verify_java(java, &runner).is_err());
+    assert!(verify_java(java, &MockRunner::default()).is_err());
+}
+
+#[test]
+fn test_verify_installed_java() {
+    let java = "/home/jane/.local/share/lapce-stable/plugins/scala/jdk/temurin-21.0.2+13-linux-x64/jdk-21.0.2+13/bin/java";
+    let command = format!("{java} -version");
+    let runner = MockRunner::default().with(&command, true, "", "openjdk version \"21.0.2\"\n");
+    assert_eq!(
+        verify_installed_java(java, &runner).unwrap().as_deref(),
+        Some("21.0.2")
+    );
+    let runner = MockRunner::default().with(
+        &command,
+        false,
+        "",
+        "cannot execute binary file: Exec format error\n",
+    );
+    assert!(verify_installed_java(java, &runner).is_err());
+    // not started, the JDK may well be fine
+    assert_eq!(
+        verify_installed_java(java, &MockRunner::default()).unwrap(),
+        None
+    );
+}
+
+// whether a cached JDK is still usable, as far as we can tell
+fn java_runs(home: &Path, os: Os) -> bool {
+    java_binary(home).is_some_and(|java| match check_installed_java(&java, os) {
+        Ok(_) => true,
+        Err(e) => {
+            logging::info(format!(
+                "cached JDK in {} is unusable: {e:#}",
+                home.display()
+            ));
+            false
+        }
+    })
+}
+
+// the archives next to the unpacked JDKs are only needed until they're
+// unpacked. Partial downloads stay so the next session can resume them
+pub fn remove_archives(dir: &Path) -> Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext != "part") {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_remove_archives() {
+    let dir = std::env::temp_dir().join("scala-lapce-test-remove-archives");
+    let _ = fs::remove_dir_all(&dir);
+    let home = dir.join("temurin-21.0.2+13-linux-x64/jdk-21.0.2+13/bin");
+    fs::create_dir_all(&home).unwrap();
+    fs::write(home.join("java"), b"").unwrap();
+    fs::write(
+        dir.join("OpenJDK21U-jdk_x64_linux_hotspot_21.0.2_13.tar.gz"),
+        b"",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("amazon-corretto-21.0.2.13.1-linux-x64.tar.gz.part"),
+        b"",
+    )
+    .unwrap();
+
+    remove_archives(&dir).unwrap();
+    assert!(!dir
+        .join("OpenJDK21U-jdk_x64_linux_hotspot_21.0.2_13.tar.gz")
+        .exists());
+    assert!(dir
+        .join("amazon-corretto-21.0.2.13.1-linux-x64.tar.gz.part")
+        .exists());
+    assert!(home.join("java").exists());
+    // twice is fine, and so is nothing having been downloaded yet
+    remove_archives(&dir).unwrap();
+    remove_archives(&dir.join("missing")).unwrap();
+}
+
+// one directory per vendor, release and platform so switching any of them
+// doesn't pick up the wrong JDK
+fn cache_dir(vendor: JdkVendor, package: JdkPackage, release: &str, platform: Platform) -> PathBuf {
+    PathBuf::from(format!(
+        "jdk/{}-{release}-{}",
+        package_name(vendor, package),
+        platform_name(platform)
+    ))
+}
+
+// a JRE lives next to the vendor's JDKs, e.g. `temurin-jre`
+fn package_name(vendor: JdkVendor, package: JdkPackage) -> String {
+    match package {
+        JdkPackage::Jdk => vendor.name().to_string(),
+        JdkPackage::Jre => format!("{}-jre", vendor.name()),
+    }
+}
+
+fn platform_name(platform: Platform) -> String {
+    let libc = match (platform.os, platform.libc) {
+        (Os::Linux, Libc::Musl) => "-musl",
+        _ => "",
+    };
+    format!("{}{libc}-{}", platform.os.name(), platform.arch.name())
+}
+
+// the archive against its published SHA-256 before it's unpacked. One that
+// doesn't match is deleted, so it's downloaded again
+fn check_archive(archive: &Path, expected: Option<&str>) -> Result<()> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    let checked = download::sha256_file(archive).and_then(|actual| {
+        if actual.eq_ignore_ascii_case(expected) {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "checksum mismatch for {}: expected {expected}, got {actual}",
+                archive.display()
+            ))
+        }
+    });
+    if checked.is_err() {
+        let _ = fs::remove_file(archive);
+    }
+    checked
+}
+
+#[test]
+fn test_check_archive() {
+    let archive = std::env::temp_dir().join("scala-lapce-test-check-archive.tar.gz");
+    let empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    fs::write(&archive, b"").unwrap();
+    assert!(check_archive(&archive, Some(empty)).is_ok());
+    assert!(check_archive(&archive, None).is_ok());
+    assert!(archive.exists());
+    // cut short, or anything else that isn't the published archive
+    assert!(check_archive(&archive, Some(&"0".repeat(64))).is_err());
+    assert!(!archive.exists());
+}
+
+// download, verify and unpack the JDK into the plugin working directory,
+// unless a previous session already did, and return its JAVA_HOME relative
+// to the working directory
+// `latest_tag` is what get_latest_release_for found for the vendor's repo,
+// the pinned release is used without one
+pub fn install(config: &JdkConfig, platform: Platform, latest_tag: Option<&str>) -> Result<String> {
+    let JdkConfig {
+        jdk_vendor: vendor,
+        jdk_version: version,
+        jdk_package: package,
+        reinstall_jdk,
+        ..
+    } = *config;
+    let release = latest_tag
+        .and_then(|tag| vendor.release_from_tag(version, tag))
+        .or_else(|| vendor.release(version).map(str::to_string))
+        .ok_or_else(|| anyhow!("{} doesn't publish a JDK {version} build", vendor.name()))?;
+    let mut dir = cache_dir(vendor, package, &release, platform);
+    let mut asset = vendor.asset(platform, package, &release)?;
+    let mut archive = Path::new("jdk").join(&asset.filename);
+
+    if reinstall_jdk {
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_file(&archive);
+    } else if let Some(home) = find_java_home(&dir, 4) {
+        if java_runs(&home, platform.os) {
+            return Ok(home.to_string_lossy().into_owned());
+        }
+        // whatever is there is unusable, start over
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    // Adoptium's API knows the exact archive, the guessed URL is only used
+    // when it can't be reached, as it normally can't where a mirror is needed
+    let mirror = config.mirror();
+    if vendor == JdkVendor::Temurin && mirror.is_none() {
+        match adoptium::latest_asset(version, package, platform) {
+            Ok((latest, latest_asset)) => {
+                dir = cache_dir(vendor, package, &latest, platform);
+                archive = Path::new("jdk").join(&latest_asset.filename);
+                asset = latest_asset;
+                if reinstall_jdk {
+                    let _ = fs::remove_dir_all(&dir);
+                    let _ = fs::remove_file(&archive);
+                } else if let Some(home) =
+                    find_java_home(&dir, 4).filter(|home| java_runs(home, platform.os))
+                {
+                    return Ok(home.to_string_lossy().into_owned());
+                }
+            }
+            Err(e) => logging::warn(format!(
+                "Adoptium API unavailable ({e:#}), downloading {}",
+                asset.url
+            )),
+        }
+    }
+
+    if let Some(mirror) = &mirror {
+        asset.url = with_mirror(&asset.url, mirror)?;
+        if let Some(checksum_url) = &asset.checksum_url {
+            asset.checksum_url = Some(with_mirror(checksum_url, mirror)?);
+        }
+    }
+
+    fs::create_dir_all(&dir)?;
+    let expected = match (&asset.checksum, &asset.checksum_url) {
+        (Some(checksum), _) => Some(checksum.clone()),
+        (None, Some(checksum_url)) => Some(download::fetch_sha256(checksum_url)?),
+        (None, None) => None,
+    };
+    // one left from an aborted session may be cut short
+    if archive.exists() {
+        if let Err(e) = check_archive(&archive, expected.as_deref()) {
+            logging::warn(format!("{e:#}, downloading it again"));
+        }
+    }
+    if !archive.exists() {
+        download::fetch(&asset.url, &archive)?;
+        check_archive(&archive, expected.as_deref())?;
+    }
+    extract::unpack(&archive, &dir)?;
+
+    let verified = find_java_home(&dir, 4)
+        .and_then(|home| java_binary(&home).map(|java| (home, java)))
+        .ok_or_else(|| anyhow!("no bin/java found in {}", asset.filename))
+        .and_then(|(home, java)| check_installed_java(&java, platform.os).map(|_| home));
+    match verified {
+        // older releases stay, another window may still run Metals on one,
+        // scala.clearCache removes them
+        Ok(home) => Ok(home.to_string_lossy().into_owned()),
+        Err(e) => {
+            // don't keep a broken JDK around for the next session to trip over
+            let _ = fs::remove_dir_all(&dir);
+            let _ = fs::remove_file(&archive);
+            Err(e.context(format!("the downloaded {} JDK doesn't run", vendor.name())))
+        }
+    }
+}
+
+#[test]
+fn test_platform_from_volt() {
+    let platform = Platform::from_volt("macos", "aarch64").unwrap();
+    assert_eq!(platform.os, Os::Mac);
+    assert_eq!(platform.arch, Arch::Aarch64);
+    assert!(Platform::from_volt("freebsd", "x86_64").is_err());
+    assert!(Platform::from_volt("linux", "riscv64").is_err());
+}
+
+#[test]
+fn test_temurin_asset() {
+    let vendor = JdkVendor::Temurin;
+    let linux_arm = Platform::from_volt("linux", "aarch64").unwrap();
+    assert_eq!(
+        vendor
+            .asset(linux_arm, JdkPackage::Jdk, "21.0.2+13")
+            .unwrap()
+            .filename,
+        "OpenJDK21U-jdk_aarch64_linux_hotspot_21.0.2_13.tar.gz"
+    );
+    let mac_arm = Platform::from_volt("macos", "aarch64").unwrap();
+    assert_eq!(
+        vendor.asset(mac_arm, JdkPackage::Jdk, "21.0.2+13").unwrap().url,
+        "https://github.com/adoptium/temurin21-binaries/releases/download/jdk-21.0.2%2B13/OpenJDK21U-jdk_aarch64_mac_hotspot_21.0.2_13.tar.gz"
+    );
+    let windows = Platform::from_volt("windows", "x86_64").unwrap();
+    assert_eq!(
+        vendor.asset(windows, JdkPackage::Jdk, "21.0.2+13").unwrap().url,
+        "https://github.com/adoptium/temurin21-binaries/releases/download/jdk-21.0.2%2B13/OpenJDK21U-jdk_x64_windows_hotspot_21.0.2_13.zip"
+    );
+    let windows_arm = Platform::from_volt("windows", "aarch64").unwrap();
+    assert!(vendor
+        .asset(windows_arm, JdkPackage::Jdk, "21.0.2+13")
+        .is_err());
+}
+
+#[test]
+fn test_build_jdk_url() {
+    assert_eq!(
+        build_jdk_url(JdkVendor::Temurin, JdkPackage::Jdk, "17.0.10+7", Os::Windows, Arch::X64, Libc::Gnu).unwrap(),
+        "https://github.com/adoptium/temurin17-binaries/releases/download/jdk-17.0.10%2B7/OpenJDK17U-jdk_x64_windows_hotspot_17.0.10_7.zip"
+    );
+    // a newer release than the pinned one needs no code change
+    assert_eq!(
+        build_jdk_url(JdkVendor::Temurin, JdkPackage::Jdk, "21.0.5+11", Os::Linux, Arch::Aarch64, Libc::Gnu).unwrap(),
+        "https://github.com/adoptium/temurin21-binaries/releases/download/jdk-21.0.5%2B11/OpenJDK21U-jdk_aarch64_linux_hotspot_21.0.5_11.tar.gz"
+    );
+    assert_eq!(
+        build_jdk_url(
+            JdkVendor::Zulu,
+            JdkPackage::Jdk,
+            "21.32.17-ca-jdk21.0.2",
+            Os::Windows,
+            Arch::Aarch64,
+            Libc::Gnu
+        )
+        .unwrap(),
+        "https://cdn.azul.com/zulu/bin/zulu21.32.17-ca-jdk21.0.2-win_aarch64.zip"
+    );
+    assert_eq!(
+        build_jdk_url(JdkVendor::Corretto, JdkPackage::Jdk, "17.0.10.7.1", Os::Mac, Arch::Aarch64, Libc::Gnu).unwrap(),
+        "https://corretto.aws/downloads/resources/17.0.10.7.1/amazon-corretto-17.0.10.7.1-macosx-aarch64.tar.gz"
+    );
+    assert_eq!(
+        build_jdk_url(JdkVendor::GraalVm, JdkPackage::Jdk, "17.0.9", Os::Mac, Arch::X64, Libc::Gnu).unwrap(),
+        "https://github.com/graalvm/graalvm-ce-builds/releases/download/jdk-17.0.9/graalvm-community-jdk-17.0.9_macos-x64_bin.tar.gz"
+    );
+    assert!(build_jdk_url(
+        JdkVendor::Corretto,
+        JdkPackage::Jdk,
+        "21.0.2.13.1",
+        Os::Windows,
+        Arch::Aarch64,
+        Libc::Gnu
+    )
+    .is_err());
+    assert_eq!(
+        build_jdk_url(JdkVendor::Temurin, JdkPackage::Jdk, "21.0.2+13", Os::Linux, Arch::X64, Libc::Musl).unwrap(),
+        "https://github.com/adoptium/temurin21-binaries/releases/download/jdk-21.0.2%2B13/OpenJDK21U-jdk_x64_alpine-linux_hotspot_21.0.2_13.tar.gz"
+    );
+    assert_eq!(
+        build_jdk_url(
+            JdkVendor::Zulu,
+            JdkPackage::Jdk,
+            "21.32.17-ca-jdk21.0.2",
+            Os::Linux,
+            Arch::Aarch64,
+            Libc::Musl
+        )
+        .unwrap(),
+        "https://cdn.azul.com/zulu/bin/zulu21.32.17-ca-jdk21.0.2-linux_musl_aarch64.tar.gz"
+    );
+    assert_eq!(
+        build_jdk_url(JdkVendor::Corretto, JdkPackage::Jdk, "21.0.2.13.1", Os::Linux, Arch::X64, Libc::Musl).unwrap(),
+        "https://corretto.aws/downloads/resources/21.0.2.13.1/amazon-corretto-21.0.2.13.1-alpine-linux-x64.tar.gz"
+    );
+    assert!(build_jdk_url(
+        JdkVendor::GraalVm,
+        JdkPackage::Jdk,
+        "21.0.2",
+        Os::Linux,
+        Arch::X64,
+        Libc::Musl
+    )
+    .is_err());
+    assert!(build_jdk_url(
+        JdkVendor::Temurin,
+        JdkPackage::Jdk,
+        "latest",
+        Os::Linux,
+        Arch::X64,
+        Libc::Gnu
+    )
+    .is_err());
+}
+
+#[test]
+fn test_release_tag_to_url() {
+    let version = JdkVersion::default();
+    let linux = Platform::from_volt("linux", "x86_64").unwrap();
+    let temurin = JdkVendor::Temurin;
+    let release = temurin.release_from_tag(version, "jdk-21.0.2+13").unwrap();
+    assert_eq!(release, "21.0.2+13");
+    assert_eq!(
+        crate::read_major_jdk_version(&release).unwrap(),
+        "OpenJDK21U"
+    );
+    assert_eq!(
+        temurin.asset(linux, JdkPackage::Jdk, &release).unwrap().url,
+        "https://github.com/adoptium/temurin21-binaries/releases/download/jdk-21.0.2%2B13/OpenJDK21U-jdk_x64_linux_hotspot_21.0.2_13.tar.gz"
+    );
+    // the first GA build has no update number
+    assert_eq!(
+        temurin.release_from_tag(version, "jdk-21+35").as_deref(),
+        Some("21+35")
+    );
+
+    let graalvm = JdkVendor::GraalVm;
+    let release = graalvm.release_from_tag(version, "jdk-21.0.2").unwrap();
+    assert_eq!(
+        graalvm.asset(linux, JdkPackage::Jdk, &release).unwrap().url,
+        "https://github.com/graalvm/graalvm-ce-builds/releases/download/jdk-21.0.2/graalvm-community-jdk-21.0.2_linux-x64_bin.tar.gz"
+    );
+    // the latest GraalVM release may well be another feature release
+    assert_eq!(graalvm.release_from_tag(version, "jdk-23.0.1"), None);
+    assert_eq!(
+        JdkVendor::Corretto
+            .release_from_tag(version, "21.0.2.13.1")
+            .as_deref(),
+        Some("21.0.2.13.1")
+    );
+    assert_eq!(temurin.release_from_tag(version, "jdk-211.0.1+1"), None);
+}
+
+#[test]
+fn test_other_vendor_assets() {
+    let version = JdkVersion::default();
+    let mac_arm = Platform::from_volt("macos", "aarch64").unwrap();
+    let linux = Platform::from_volt("linux", "x86_64").unwrap();
+    let windows = Platform::from_volt("windows", "x86_64").unwrap();
+    let asset = |vendor: JdkVendor, platform| {
+        vendor
+            .asset(platform, JdkPackage::Jdk, vendor.release(version).unwrap())
+            .unwrap()
+    };
+    assert_eq!(
+        asset(JdkVendor::Zulu, mac_arm).url,
+        "https://cdn.azul.com/zulu/bin/zulu21.32.17-ca-jdk21.0.2-macosx_aarch64.tar.gz"
+    );
+    assert_eq!(
+        asset(JdkVendor::Corretto, windows).url,
+        "https://corretto.aws/downloads/resources/21.0.2.13.1/amazon-corretto-21.0.2.13.1-windows-x64-jdk.zip"
+    );
+    let graalvm = asset(JdkVendor::GraalVm, linux);
+    assert_eq!(
+        graalvm.url,
+        "https://github.com/graalvm/graalvm-ce-builds/releases/download/jdk-21.0.2/graalvm-community-jdk-21.0.2_linux-x64_bin.tar.gz"
+    );
+    assert_eq!(
+        graalvm.checksum_url.as_deref(),
+        Some("https://github.com/graalvm/graalvm-ce-builds/releases/download/jdk-21.0.2/graalvm-community-jdk-21.0.2_linux-x64_bin.tar.gz.sha256")
+    );
+}
+
+#[test]
+fn test_jdk_version() {
+    let config = JdkConfig::from_options(Some(&serde_json::json!({ "jdkVersion": "17" }))).unwrap();
+    assert_eq!(config.jdk_version, JdkVersion(17));
+    assert_eq!(JdkConfig::default().jdk_version, JdkVersion(21));
+    let err = JdkConfig::from_options(Some(&serde_json::json!({ "jdkVersion": "8" }))).unwrap_err();
+    assert!(format!("{err:#}").contains("Metals supports JDK 11, 17, 21"));
+
+    let linux = Platform::from_volt("linux", "x86_64").unwrap();
+    let release = JdkVendor::Temurin.release(JdkVersion(17)).unwrap();
+    assert_eq!(
+        JdkVendor::Temurin
+            .asset(linux, JdkPackage::Jdk, release)
+            .unwrap()
+            .url,
+        "https://github.com/adoptium/temurin17-binaries/releases/download/jdk-17.0.10%2B7/OpenJDK17U-jdk_x64_linux_hotspot_17.0.10_7.tar.gz"
+    );
+    assert_eq!(JdkVendor::GraalVm.release(JdkVersion(11)), None);
+}
+
+#[test]
+fn test_jdk_config_from_options() {
+    let config = JdkConfig::from_options(Some(&serde_json::json!({
+        "jdkVendor": "corretto",
+        "lsp": { "serverPath": "" },
+    })))
+    .unwrap();
+    assert_eq!(config.jdk_vendor, JdkVendor::Corretto);
+    assert!(!config.disable_auto_jdk_download);
+    let config = JdkConfig::from_options(Some(&serde_json::json!({
+        "disableAutoJdkDownload": true,
+    })))
+    .unwrap();
+    assert!(config.disable_auto_jdk_download);
+    assert_eq!(
+        JdkConfig::from_options(Some(&serde_json::json!({}))).unwrap(),
+        JdkConfig::default()
+    );
+    assert_eq!(JdkConfig::default().jdk_vendor, JdkVendor::Temurin);
+    assert!(JdkConfig::from_options(Some(&serde_json::json!({ "jdkVendor": "oracle" }))).is_err());
+}
+
+#[test]
+fn test_configured_java_home() {
+    let dir = std::env::temp_dir().join("scala-lapce-test-configured-java-home");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("bin")).unwrap();
+    let home = dir.to_string_lossy().into_owned();
+
+    let config = JdkConfig::from_options(Some(
+        &serde_json::json!({ "javaHome": format!(" {home} ") }),
+    ))
+    .unwrap();
+    assert_eq!(config.configured_java_home(), Some(home.as_str()));
+    assert!(check_java_home(&home).is_err());
+    fs::write(dir.join("bin/java"), b"").unwrap();
+    assert!(check_java_home(&home).is_ok());
+
+    let config = JdkConfig::from_options(Some(&serde_json::json!({ "javaHome": "" }))).unwrap();
+    assert_eq!(config.configured_java_home(), None);
+    assert_eq!(JdkConfig::default().configured_java_home(), None);
+}
+
+#[test]
+fn test_find_java_home() {
+    let dir = std::env::temp_dir().join("scala-lapce-test-find-java-home");
+    let _ = fs::remove_dir_all(&dir);
+    let home = dir.join("zulu21-macosx_aarch64/zulu-21.jdk/Contents/Home");
+    fs::create_dir_all(home.join("bin")).unwrap();
+    fs::write(home.join("bin/java"), b"").unwrap();
+    assert_eq!(find_java_home(&dir, 4), Some(home));
+    assert_eq!(find_java_home(&dir, 2), None);
+}
+
+#[test]
+fn test_windows_zip_java_home() {
+    use std::io::Write;
+
+    let dir = std::env::temp_dir().join("scala-lapce-test-windows-zip");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let windows = Platform::from_volt("windows", "x86_64").unwrap();
+    let asset = JdkVendor::Temurin
+        .asset(windows, JdkPackage::Jdk, "21.0.2+13")
+        .unwrap();
+    let archive = dir.join(&asset.filename);
+    let mut writer = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+    for file in ["bin/java.exe", "bin/javaw.exe", "lib/modules"] {
+        writer
+            .start_file(format!("jdk-21.0.2+13/{file}"), Default::default())
+            .unwrap();
+        writer.write_all(b"MZ").unwrap();
+    }
+    writer.finish().unwrap();
+
+    extract::unpack(&archive, &dir).unwrap();
+    let home = find_java_home(&dir, 4).unwrap();
+    assert_eq!(home, dir.join("jdk-21.0.2+13"));
+    assert_eq!(java_binary(&home), Some(home.join("bin/java.exe")));
+}
+
+#[test]
+fn test_jre() {
+    let config =
+        JdkConfig::from_options(Some(&serde_json::json!({ "jdkPackage": "jre" }))).unwrap();
+    assert_eq!(config.jdk_package, JdkPackage::Jre);
+    assert_eq!(JdkConfig::default().jdk_package, JdkPackage::Jdk);
+    assert!(JdkConfig::from_options(Some(&serde_json::json!({ "jdkPackage": "jmod" }))).is_err());
+
+    let linux = Platform::from_volt("linux", "x86_64").unwrap();
+    let asset = JdkVendor::Temurin
+        .asset(linux, JdkPackage::Jre, "21.0.2+13")
+        .unwrap();
+    assert_eq!(
+        asset.url,
+        "https://github.com/adoptium/temurin21-binaries/releases/download/jdk-21.0.2%2B13/OpenJDK21U-jre_x64_linux_hotspot_21.0.2_13.tar.gz"
+    );
+    assert_eq!(
+        asset.checksum_url.as_deref(),
+        Some("https://github.com/adoptium/temurin21-binaries/releases/download/jdk-21.0.2%2B13/OpenJDK21U-jre_x64_linux_hotspot_21.0.2_13.tar.gz.sha256.txt")
+    );
+    let release = JdkVendor::Zulu.release(JdkVersion::default()).unwrap();
+    assert!(JdkVendor::Zulu
+        .asset(linux, JdkPackage::Jre, release)
+        .is_err());
+    let windows_arm = Platform::from_volt("windows", "aarch64").unwrap();
+    assert!(JdkVendor::Temurin
+        .asset(windows_arm, JdkPackage::Jre, "21.0.2+13")
+        .is_err());
+
+    // JREs are kept apart from the JDKs
+    let jre = cache_dir(JdkVendor::Temurin, JdkPackage::Jre, "21.0.1+12", linux);
+    assert_eq!(jre, Path::new("jdk/temurin-jre-21.0.1+12-linux-x64"));
+}
+
+#[test]
+fn test_cache_dir() {
+    let mac_arm = Platform::from_volt("macos", "aarch64").unwrap();
+    let linux = Platform::from_volt("linux", "x86_64").unwrap();
+    assert_eq!(
+        cache_dir(JdkVendor::Temurin, JdkPackage::Jdk, "21.0.2+13", mac_arm),
+        Path::new("jdk/temurin-21.0.2+13-mac-aarch64")
+    );
+    assert_ne!(
+        cache_dir(JdkVendor::Temurin, JdkPackage::Jdk, "21.0.2+13", linux),
+        cache_dir(JdkVendor::Zulu, JdkPackage::Jdk, "21.0.2+13", linux)
+    );
+}
+
+#[test]
+fn test_java_major_version() {
+    assert_eq!(java_major_version("21.0.2"), Some(21));
+    assert_eq!(java_major_version("1.8.0_381"), Some(8));
+    assert_eq!(java_major_version("22-ea"), Some(22));
+    assert_eq!(java_major_version("11.0.20.1"), Some(11));
+    assert_eq!(java_major_version(""), None);
+    assert!(is_supported_java("11.0.22"));
+    assert!(!is_supported_java("1.8.0_381"));
+    assert!(!is_supported_java(""));
+}
@@ -0,0 +1,674 @@
+use anyhow::{anyhow, Context, Result};
+use lapce_plugin::psp_types::lsp_types::{DocumentSelector, Url};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::jdk::Os;
+use crate::proxy;
+use crate::server::Server;
+
+// how the editor talks to Metals. Only stdio works: start_lsp always
+// speaks LSP over the process' stdin and stdout, and a WASI plugin can't
+// open sockets to relay a TCP connection itself
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    #[default]
+    Stdio,
+    Tcp,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct TransportConfig {
+    #[serde(default)]
+    pub transport: Transport,
+}
+
+impl TransportConfig {
+    pub fn from_options(options: Option<&Value>) -> Result<TransportConfig> {
+        match options {
+            Some(options) => serde_json::from_value(options.clone())
+                .context("invalid transport, expected stdio or tcp"),
+            None => Ok(TransportConfig::default()),
+        }
+    }
+
+    // fail up front rather than start a server nothing can connect to
+    pub fn check(&self) -> Result<()> {
+        match self.transport {
+            Transport::Stdio => Ok(()),
+            Transport::Tcp => Err(anyhow!(
+                "transport tcp isn't supported, Lapce only runs language servers over \
+                 stdio and plugins can't open sockets, remove the setting to use stdio"
+            )),
+        }
+    }
+}
+
+#[test]
+fn test_transport() {
+    let config = |transport: &str| {
+        TransportConfig::from_options(Some(&serde_json::json!({ "transport": transport })))
+    };
+    assert!(TransportConfig::default().check().is_ok());
+    assert!(config("stdio").unwrap().check().is_ok());
+    assert!(config("tcp").unwrap().check().is_err());
+    assert!(config("pipe").is_err());
+}
+
+// everything needed to spawn the language server process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaunchOptions {
+    pub server_uri: Url,
+    pub server_args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    // serverEnv, set over whatever the plugin put in `env`
+    pub user_env: BTreeMap<String, String>,
+}
+
+impl LaunchOptions {
+    pub fn new(server_uri: Url, server_args: Vec<String>) -> Self {
+        Self {
+            server_uri,
+            server_args,
+            env: BTreeMap::new(),
+            user_env: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_env(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.env.insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn with_user_env(mut self, env: &BTreeMap<String, String>) -> Self {
+        self.user_env.extend(env.clone());
+        self
+    }
+
+    fn environment(&self) -> BTreeMap<String, String> {
+        let mut env = self.env.clone();
+        env.extend(self.user_env.clone());
+        env
+    }
+
+    // start_lsp can't pass an environment to the server, so when one is
+    // needed the server is spawned through `env` (or `cmd /C set` on Windows)
+    fn command(&self, os: Os) -> Result<(Url, Vec<String>)> {
+        let env = self.environment();
+        if env.is_empty() {
+            return Ok((self.server_uri.clone(), self.server_args.clone()));
+        }
+
+        let server = server_program(&self.server_uri, os)?;
+        let mut args = vec![];
+        match os {
+            Os::Windows => {
+                args.push("/C".to_string());
+                for (key, value) in &env {
+                    args.extend(cmd_set(key, value)?);
+                }
+                // a batch file like the Metals launcher parses its
+                // arguments once more, so they're escaped for that too
+                let batch = [".bat", ".cmd"]
+                    .iter()
+                    .any(|ext| server.to_ascii_lowercase().ends_with(ext));
+                args.push(server);
+                for arg in &self.server_args {
+                    let escaped = cmd_escape(arg)
+                        .and_then(|escaped| {
+                            if batch {
+                                cmd_escape(&escaped)
+                            } else {
+                                Ok(escaped)
+                            }
+                        })
+                        .with_context(|| {
+                            format!("server argument {} can't be passed on", proxy::redact(arg))
+                        })?;
+                    args.push(escaped);
+                }
+                Ok((Url::parse("urn:cmd")?, args))
+            }
+            _ => {
+                for (key, value) in &env {
+                    args.push(format!("{key}={value}"));
+                }
+                args.push(server);
+                args.extend(self.server_args.iter().cloned());
+                Ok((Url::parse("urn:env")?, args))
+            }
+        }
+    }
+
+    pub fn into_server(
+        self,
+        os: Os,
+        document_selector: DocumentSelector,
+        options: Option<Value>,
+    ) -> Result<Server> {
+        let (server_uri, server_args) = self.command(os)?;
+        Ok(Server {
+            server_uri,
+            server_args,
+            document_selector,
+            options,
+            base_options: None,
+            installs: vec![],
+            metals_version: None,
+        })
+    }
+}
+
+// cmd parses its command line itself, unescaped these would end the
+// server's command and start another one
+const CMD_METACHARACTERS: [char; 5] = ['&', '|', '<', '>', '^'];
+
+// `arg` so cmd passes it on as it is. The host quotes an argument with a
+// space in it, cmd leaves anything in quotes alone, otherwise `^` keeps
+// cmd from acting on a metacharacter. The host escapes quotes inside an
+// argument in a way cmd doesn't understand, those can't be passed
+fn cmd_escape(arg: &str) -> Result<String> {
+    if arg.contains('"') {
+        return Err(anyhow!("cmd can't be given a quote"));
+    }
+    if arg.contains([' ', '\t']) {
+        return Ok(arg.to_string());
+    }
+    let mut escaped = String::with_capacity(arg.len());
+    for c in arg.chars() {
+        if CMD_METACHARACTERS.contains(&c) {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    Ok(escaped)
+}
+
+// `set "K=V" &&` as arguments for cmd. cmd_escape's quoting only comes
+// from the host, and without quotes the space before `&&` would end up in
+// the value, so there `&&` goes right after it
+fn cmd_set(key: &str, value: &str) -> Result<Vec<String>> {
+    let assignment = cmd_escape(&format!("{key}={value}"))
+        .with_context(|| format!("serverEnv {key} can't be set"))?;
+    Ok(if assignment.contains([' ', '\t']) {
+        vec!["set".to_string(), assignment, "&&".to_string()]
+    } else {
+        vec!["set".to_string(), format!("{assignment}&&")]
+    })
+}
+
+// the program name the host should spawn for a server uri
+// (`urn:` for PATH lookups, `file:` for absolute paths)
+fn server_program(uri: &Url, os: Os) -> Result<String> {
+    match uri.scheme() {
+        "urn" => Ok(uri.path().to_string()),
+        "file" => host_path(uri, os),
+        scheme => Err(anyhow!("unsupported server uri scheme {scheme}")),
+    }
+}
+
+// characters that would otherwise end the path or be taken as an escape
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+// `file:` url for an absolute host path, which may be a unix path, a
+// Windows drive path or a UNC share. Url::from_file_path can't be used for
+// the same reason as in host_path
+pub fn file_uri(path: &str) -> Result<Url> {
+    let path = path.trim();
+    let drive = path.as_bytes().get(..3).is_some_and(|prefix| {
+        prefix[0].is_ascii_alphabetic() && prefix[1] == b':' && matches!(prefix[2], b'\\' | b'/')
+    });
+    let (host, path) = if let Some(unc) = path
+        .strip_prefix("\\\\")
+        .or_else(|| path.strip_prefix("//"))
+    {
+        let (host, share) = unc.split_once(['\\', '/']).unwrap_or((unc, ""));
+        (host, format!("/{share}"))
+    } else if drive {
+        ("", format!("/{path}"))
+    } else if path.starts_with('/') {
+        ("", path.to_string())
+    } else {
+        return Err(anyhow!(
+            "{path:?} is neither an absolute path nor a program name"
+        ));
+    };
+    let path: Vec<String> = path
+        .split(['\\', '/'])
+        .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+        .collect();
+    Ok(Url::parse(&format!("file://{host}{}", path.join("/")))?)
+}
+
+// serverPath is either a program looked up on PATH, which the host
+// spawns for `urn:` uris, or a path to one
+pub fn server_uri(server_path: &str) -> Result<Url> {
+    let server_path = server_path.trim();
+    if server_path.contains(['/', '\\']) {
+        file_uri(server_path)
+    } else {
+        Ok(Url::parse(&format!("urn:{server_path}"))?)
+    }
+}
+
+#[test]
+fn test_server_uri() {
+    assert_eq!(server_uri("metals").unwrap().as_str(), "urn:metals");
+    assert_eq!(
+        server_uri("/opt/metals/bin/metals").unwrap().as_str(),
+        "file:///opt/metals/bin/metals"
+    );
+    assert_eq!(
+        server_uri("/home/jane/my tools/metals#2").unwrap().as_str(),
+        "file:///home/jane/my%20tools/metals%232"
+    );
+    assert_eq!(
+        server_uri("C:\\Program Files\\Metals\\metals.bat")
+            .unwrap()
+            .as_str(),
+        "file:///C:/Program%20Files/Metals/metals.bat"
+    );
+    assert_eq!(
+        server_uri("D:/tools/metals.bat").unwrap().as_str(),
+        "file:///D:/tools/metals.bat"
+    );
+    assert_eq!(
+        server_uri("\\\\fileserver\\tools\\metals.bat")
+            .unwrap()
+            .as_str(),
+        "file://fileserver/tools/metals.bat"
+    );
+    assert!(server_uri("bin/metals").is_err());
+    assert!(server_uri("./metals").is_err());
+}
+
+// Metals' entry point, for running it without the launcher
+pub const METALS_MAIN: &str = "scala.meta.metals.Main";
+
+// the longest command line that's passed as is. cmd, which serverEnv
+// wraps the server in on Windows, takes 8191 characters, and Linux caps a
+// single argument like the classpath at 128KiB
+fn max_command_line(os: Os) -> usize {
+    match os {
+        Os::Windows => 8191,
+        _ => 128 * 1024,
+    }
+}
+
+// `java [jvm args] -cp <classpath> <main class>` for a server run straight
+// from its jars
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JavaLaunch {
+    pub java: Url,
+    pub jvm_args: Vec<String>,
+    pub classpath: Vec<String>,
+    pub main_class: String,
+}
+
+impl JavaLaunch {
+    pub fn new(java: Url, classpath: Vec<String>, main_class: &str) -> Self {
+        Self {
+            java,
+            jvm_args: vec![],
+            classpath,
+            main_class: main_class.to_string(),
+        }
+    }
+
+    // launcher style `-J` flags work too, java gets them without the prefix
+    pub fn with_jvm_args(mut self, args: impl IntoIterator<Item = String>) -> Self {
+        self.jvm_args.extend(
+            args.into_iter()
+                .map(|arg| arg.strip_prefix("-J").map(str::to_string).unwrap_or(arg)),
+        );
+        self
+    }
+
+    // the classpath goes into `argfile`, written to the sandbox path and
+    // passed by its host path, when the command line would be too long
+    // for the host. Java reads `@file` arguments from 9 on, and Metals
+    // needs 11 anyway
+    pub fn build(self, os: Os, argfile: &Path, argfile_host: &str) -> Result<LaunchOptions> {
+        let separator = match os {
+            Os::Windows => ";",
+            _ => ":",
+        };
+        let classpath = self.classpath.join(separator);
+        let length: usize = self
+            .jvm_args
+            .iter()
+            .chain([&classpath, &self.main_class])
+            .map(|arg| arg.len() + 1)
+            .sum();
+        let mut args = self.jvm_args;
+        if length + self.java.as_str().len() > max_command_line(os) {
+            if let Some(dir) = argfile.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(argfile, format!("-cp\n{}\n", argfile_quote(&classpath))).with_context(
+                || format!("couldn't write the classpath to {}", argfile.display()),
+            )?;
+            args.push(format!("@{argfile_host}"));
+        } else {
+            args.push("-cp".to_string());
+            args.push(classpath);
+        }
+        args.push(self.main_class);
+        Ok(LaunchOptions::new(self.java, args))
+    }
+}
+
+// java argfiles treat backslashes in quotes as escapes, which Windows
+// paths are full of
+fn argfile_quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// the java in `home`, or the one on PATH
+pub fn java_uri(home: Option<&str>, os: Os) -> Result<Url> {
+    match (home, os) {
+        (Some(home), Os::Windows) => file_uri(&format!(
+            "{}\\bin\\java.exe",
+            home.trim_end_matches(['\\', '/'])
+        )),
+        (Some(home), _) => file_uri(&format!("{}/bin/java", home.trim_end_matches('/'))),
+        (None, _) => Ok(Url::parse("urn:java")?),
+    }
+}
+
+#[test]
+fn test_java_launch() {
+    let dir = std::env::temp_dir().join("scala-lapce-test-java-launch");
+    let _ = std::fs::remove_dir_all(&dir);
+    let argfile = dir.join("classpath.args");
+    let java = java_uri(Some("/opt/jdk-21.0.2+13"), Os::Linux).unwrap();
+    assert_eq!(java.as_str(), "file:///opt/jdk-21.0.2+13/bin/java");
+    let classpath = vec![
+        "/home/jane/.cache/coursier/v1/metals_2.13-1.3.0.jar".to_string(),
+        "/home/jane/.cache/coursier/v1/scala-library-2.13.12.jar".to_string(),
+    ];
+    let launch = JavaLaunch::new(java, classpath, METALS_MAIN)
+        .with_jvm_args(["-J-Xss4m".to_string(), "-Dmetals.verbose=true".to_string()])
+        .build(Os::Linux, &argfile, "/unused")
+        .unwrap();
+    assert_eq!(
+        launch.server_args,
+        [
+            "-Xss4m",
+            "-Dmetals.verbose=true",
+            "-cp",
+            "/home/jane/.cache/coursier/v1/metals_2.13-1.3.0.jar:\
+             /home/jane/.cache/coursier/v1/scala-library-2.13.12.jar",
+            METALS_MAIN
+        ]
+    );
+    assert!(!argfile.exists());
+    assert_eq!(java_uri(None, Os::Linux).unwrap().as_str(), "urn:java");
+}
+
+#[test]
+fn test_java_launch_argfile() {
+    let dir = std::env::temp_dir().join("scala-lapce-test-java-launch-argfile");
+    let _ = std::fs::remove_dir_all(&dir);
+    let argfile = dir.join("metals/metals_2.13-1.3.0/classpath.args");
+    let java = java_uri(Some("C:\\Program Files\\Java\\jdk-21\\"), Os::Windows).unwrap();
+    assert_eq!(
+        java.as_str(),
+        "file:///C:/Program%20Files/Java/jdk-21/bin/java.exe"
+    );
+    // a few hundred jars deep in the user's profile, like Metals' own
+    let classpath: Vec<String> = (0..300)
+        .map(|i| format!("C:\\Users\\Jane Doe\\AppData\\Local\\Coursier\\Cache\\v1\\lib-{i}.jar"))
+        .collect();
+    let host =
+        "C:\\Users\\Jane Doe\\lapce\\plugins\\scala\\metals\\metals_2.13-1.3.0\\classpath.args";
+    let launch = JavaLaunch::new(java, classpath.clone(), METALS_MAIN)
+        .with_jvm_args(["-J-Xss4m".to_string()])
+        .build(Os::Windows, &argfile, host)
+        .unwrap();
+    assert_eq!(
+        launch.server_args,
+        [
+            "-Xss4m".to_string(),
+            format!("@{host}"),
+            METALS_MAIN.to_string()
+        ]
+    );
+    let contents = std::fs::read_to_string(&argfile).unwrap();
+    let (flag, quoted) = contents.trim_end().split_once('\n').unwrap();
+    assert_eq!(flag, "-cp");
+    assert!(quoted.starts_with("\"C:\\\\Users\\\\Jane Doe\\\\AppData"));
+    // what java reads back out of the quotes
+    let unquoted = quoted[1..quoted.len() - 1].replace("\\\\", "\\");
+    assert_eq!(unquoted, classpath.join(";"));
+}
+
+// a path relative to the plugin working directory as a url on the host.
+// The directory needs its trailing slash, `join` would replace its last
+// segment otherwise
+pub fn plugin_uri(volt_uri: &Url, relative: &str) -> Result<Url> {
+    let mut dir = volt_uri.clone();
+    if !dir.path().ends_with('/') {
+        dir.set_path(&format!("{}/", dir.path()));
+    }
+    dir.join(relative)
+        .with_context(|| format!("can't locate {relative} in the plugin directory {volt_uri}"))
+}
+
+// like plugin_uri for a file we made, so a launcher that went missing fails
+// here rather than as a server the host can't spawn
+pub fn plugin_file_uri(volt_uri: &Url, relative: &str) -> Result<Url> {
+    if !Path::new(relative).is_file() {
+        return Err(anyhow!(
+            "{relative} is missing from the plugin directory, clear the cache and restart \
+             to download it again"
+        ));
+    }
+    plugin_uri(volt_uri, relative)
+}
+
+#[test]
+fn test_plugin_file_uri() {
+    let volt = Url::parse("file:///home/jane/.local/share/lapce-stable/plugins/scala/").unwrap();
+    assert_eq!(
+        plugin_file_uri(&volt, "volt.toml").unwrap().as_str(),
+        "file:///home/jane/.local/share/lapce-stable/plugins/scala/volt.toml"
+    );
+    assert_eq!(
+        plugin_uri(&volt, "jdk/temurin-21.0.2+13-linux-x64/jdk-21.0.2+13")
+            .unwrap()
+            .as_str(),
+        "file:///home/jane/.local/share/lapce-stable/plugins/scala/jdk/temurin-21.0.2+13-linux-x64/jdk-21.0.2+13"
+    );
+    let unslashed = Url::parse("file:///C:/Users/Jane/AppData/Local/lapce/plugins/scala").unwrap();
+    assert_eq!(
+        plugin_uri(&unslashed, "metals/metals_2.13-1.3.0/metals.bat")
+            .unwrap()
+            .as_str(),
+        "file:///C:/Users/Jane/AppData/Local/lapce/plugins/scala/metals/metals_2.13-1.3.0/metals.bat"
+    );
+    let e = plugin_file_uri(&volt, "metals/metals_2.13-0.0.0/metals").unwrap_err();
+    assert!(e
+        .to_string()
+        .contains("metals/metals_2.13-0.0.0/metals is missing"));
+}
+
+// Url::to_file_path assumes the platform we're compiled for, which under
+// WASI isn't the one Lapce runs on
+pub fn host_path(uri: &Url, os: Os) -> Result<String> {
+    let path = percent_decode_str(uri.path()).decode_utf8()?;
+    Ok(match (os, uri.host_str()) {
+        (Os::Windows, Some(host)) if !host.is_empty() => {
+            format!("\\\\{host}{}", path.replace('/', "\\"))
+        }
+        (Os::Windows, _) => path.trim_start_matches('/').replace('/', "\\"),
+        _ => path.into_owned(),
+    })
+}
+
+#[test]
+fn test_managed_jdk_sets_java_home() {
+    let server = Url::parse("file:///home/user/.local/share/lapce/plugins/scala/metals").unwrap();
+    let launch = LaunchOptions::new(server, vec!["-Dmetals.verbose".to_string()])
+        .with_env("JAVA_HOME", "/opt/jdk-21.0.2+13");
+    assert_eq!(
+        launch.env.get("JAVA_HOME").map(String::as_str),
+        Some("/opt/jdk-21.0.2+13")
+    );
+
+    let (uri, args) = launch.command(Os::Linux).unwrap();
+    assert_eq!(uri.as_str(), "urn:env");
+    assert_eq!(
+        args,
+        [
+            "JAVA_HOME=/opt/jdk-21.0.2+13",
+            "/home/user/.local/share/lapce/plugins/scala/metals",
+            "-Dmetals.verbose",
+        ]
+    );
+}
+
+#[test]
+fn test_server_env_precedence() {
+    let server = Url::parse("file:///home/user/.local/share/lapce/plugins/scala/metals").unwrap();
+    let user_env = BTreeMap::from([
+        ("JAVA_HOME".to_string(), "/usr/lib/jvm/zulu-17".to_string()),
+        ("COURSIER_CACHE".to_string(), "/srv/coursier".to_string()),
+    ]);
+    // set before and after the plugin's own, the user's still wins
+    let launch = LaunchOptions::new(server.clone(), vec![])
+        .with_user_env(&user_env)
+        .with_env("JAVA_HOME", "/opt/jdk-21.0.2+13");
+    let (_, args) = launch.command(Os::Linux).unwrap();
+    assert_eq!(
+        args,
+        [
+            "COURSIER_CACHE=/srv/coursier",
+            "JAVA_HOME=/usr/lib/jvm/zulu-17",
+            "/home/user/.local/share/lapce/plugins/scala/metals",
+        ]
+    );
+
+    let launch = LaunchOptions::new(server, vec![]).with_user_env(&user_env);
+    assert_eq!(launch.command(Os::Linux).unwrap().0.as_str(), "urn:env");
+}
+
+#[test]
+fn test_cmd_escapes_metacharacters() {
+    let server = Url::parse("file:///C:/Users/jane/lapce/scala/metals.bat").unwrap();
+    let args = vec![
+        "-J-Xss4m&calc.exe".to_string(),
+        "-J-Dhttp.nonProxyHosts=localhost|*.corp.example".to_string(),
+        "-J-Dworksheet.title=a & b".to_string(),
+    ];
+    let launch = LaunchOptions::new(server, args.clone()).with_env("JAVA_HOME", "C:\\jdk-21");
+    let (_, escaped) = launch.command(Os::Windows).unwrap();
+    // once for cmd and once for metals.bat, quoted by the host
+    assert_eq!(
+        escaped[4..],
+        [
+            "-J-Xss4m^^^&calc.exe",
+            "-J-Dhttp.nonProxyHosts=localhost^^^|*.corp.example",
+            "-J-Dworksheet.title=a & b",
+        ]
+    );
+    let java = Url::parse("file:///C:/jdk-21/bin/java.exe").unwrap();
+    let launch = LaunchOptions::new(java, args.clone()).with_env("JAVA_HOME", "C:\\jdk-21");
+    assert_eq!(
+        launch.command(Os::Windows).unwrap().1[4],
+        "-J-Xss4m^&calc.exe"
+    );
+    // `env` runs the server itself, nothing parses the arguments
+    let launch = LaunchOptions::new(
+        Url::parse("file:///opt/metals").unwrap(),
+        args[..1].to_vec(),
+    )
+    .with_env("JAVA_HOME", "/opt/jdk-21");
+    assert_eq!(
+        launch.command(Os::Linux).unwrap().1,
+        ["JAVA_HOME=/opt/jdk-21", "/opt/metals", "-J-Xss4m&calc.exe"]
+    );
+
+    let server = Url::parse("file:///C:/Users/jane/lapce/scala/metals.bat").unwrap();
+    let launch = LaunchOptions::new(server.clone(), vec!["-J-Dquote=\"".to_string()])
+        .with_env("JAVA_HOME", "C:\\jdk-21");
+    assert!(launch.command(Os::Windows).is_err());
+    // set, not run
+    let user_env = BTreeMap::from([("X".to_string(), "a&calc".to_string())]);
+    let launch = LaunchOptions::new(server.clone(), vec![]).with_user_env(&user_env);
+    assert_eq!(
+        launch.command(Os::Windows).unwrap().1[1..3],
+        ["set", "X=a^&calc&&"]
+    );
+    let user_env = BTreeMap::from([("X".to_string(), "a\" & calc".to_string())]);
+    let launch = LaunchOptions::new(server, vec![]).with_user_env(&user_env);
+    assert!(launch.command(Os::Windows).is_err());
+}
+
+#[test]
+fn test_cmd_set() {
+    let server = Url::parse("file:///C:/Users/jane/lapce/scala/metals.bat").unwrap();
+    let launch = LaunchOptions::new(server, vec!["-J-Xss4m".to_string()])
+        .with_env("COURSIER_REPOSITORIES", "ivy2Local|central")
+        .with_env("JAVA_HOME", "C:\\Users\\Jane Doe\\lapce\\scala\\jdk-21");
+    let (uri, args) = launch.command(Os::Windows).unwrap();
+    assert_eq!(uri.as_str(), "urn:cmd");
+    assert_eq!(
+        args,
+        [
+            "/C",
+            "set",
+            "COURSIER_REPOSITORIES=ivy2Local^|central&&",
+            "set",
+            "JAVA_HOME=C:\\Users\\Jane Doe\\lapce\\scala\\jdk-21",
+            "&&",
+            "C:\\Users\\jane\\lapce\\scala\\metals.bat",
+            "-J-Xss4m",
+        ]
+    );
+}
+
+#[test]
+fn test_launch_without_env_is_unchanged() {
+    let server = Url::parse("urn:metals").unwrap();
+    let launch = LaunchOptions::new(server.clone(), vec![]);
+    assert_eq!(launch.command(Os::Mac).unwrap(), (server, vec![]));
+}
+
+#[test]
+fn test_host_path() {
+    let uri = Url::parse("file:///C:/Users/Jane%20Doe/lapce/jdk").unwrap();
+    assert_eq!(
+        host_path(&uri, Os::Windows).unwrap(),
+        "C:\\Users\\Jane Doe\\lapce\\jdk"
+    );
+    let uri = server_uri("\\\\fileserver\\tools\\metals.bat").unwrap();
+    assert_eq!(
+        host_path(&uri, Os::Windows).unwrap(),
+        "\\\\fileserver\\tools\\metals.bat"
+    );
+    let uri = server_uri("C:\\Program Files\\Metals\\metals.bat").unwrap();
+    assert_eq!(
+        host_path(&uri, Os::Windows).unwrap(),
+        "C:\\Program Files\\Metals\\metals.bat"
+    );
+    let uri = Url::parse("file:///home/jane/jdk-21.0.2%2B13").unwrap();
+    assert_eq!(
+        host_path(&uri, Os::Linux).unwrap(),
+        "/home/jane/jdk-21.0.2+13"
+    );
+}
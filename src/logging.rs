@@ -0,0 +1,95 @@
+use anyhow::{Context, Result};
+use lapce_plugin::{psp_types::lsp_types::MessageType, PLUGIN_RPC};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// least to most verbose, each level includes the ones before it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    #[default]
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn message_type(self) -> MessageType {
+        match self {
+            Level::Error => MessageType::ERROR,
+            Level::Warn => MessageType::WARNING,
+            Level::Info => MessageType::INFO,
+            Level::Debug => MessageType::LOG,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogConfig {
+    #[serde(default)]
+    pub log_level: Level,
+}
+
+impl LogConfig {
+    pub fn from_options(options: Option<&Value>) -> Result<LogConfig> {
+        match options {
+            Some(options) => serde_json::from_value(options.clone()).context("invalid logLevel"),
+            None => Ok(LogConfig::default()),
+        }
+    }
+}
+
+// set once the options are known, Warn until then
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+fn enabled(level: Level, threshold: u8) -> bool {
+    level as u8 <= threshold
+}
+
+// goes to Lapce's log rather than a popup, print and eprint don't reach
+// anyone under WASI
+pub fn log(level: Level, message: impl Display) {
+    if enabled(level, LEVEL.load(Ordering::Relaxed)) {
+        PLUGIN_RPC.window_log_message(level.message_type(), format!("[scala] {message}"));
+    }
+}
+
+pub fn error(message: impl Display) {
+    log(Level::Error, message);
+}
+
+pub fn warn(message: impl Display) {
+    log(Level::Warn, message);
+}
+
+pub fn info(message: impl Display) {
+    log(Level::Info, message);
+}
+
+pub fn debug(message: impl Display) {
+    log(Level::Debug, message);
+}
+
+#[test]
+fn test_log_level() {
+    assert_eq!(LogConfig::default().log_level, Level::Warn);
+    let config =
+        LogConfig::from_options(Some(&serde_json::json!({ "logLevel": "debug" }))).unwrap();
+    assert_eq!(config.log_level, Level::Debug);
+    assert!(LogConfig::from_options(Some(&serde_json::json!({ "logLevel": "trace" }))).is_err());
+
+    let warn = Level::Warn as u8;
+    assert!(enabled(Level::Error, warn));
+    assert!(enabled(Level::Warn, warn));
+    assert!(!enabled(Level::Info, warn));
+    assert!(!enabled(Level::Debug, warn));
+    assert!(enabled(Level::Debug, Level::Debug as u8));
+}
@@ -4,35 +4,324 @@
 #![deny(clippy::print_stdout)]
 #![deny(clippy::print_stderr)]
 
-use anyhow::Result;
-use github_release_check::{self, GitHub};
+mod adoptium;
+mod build_tool;
+mod commands;
+mod download;
+mod extract;
+mod github;
+mod info_cache;
+mod jdk;
+mod launch;
+mod logging;
+mod metals;
+mod options;
+mod progress;
+mod proxy;
+mod runner;
+mod sbt;
+mod scala;
+mod selector;
+mod server;
+mod version;
+
+use anyhow::{anyhow, Context, Result};
 use lapce_plugin::{
     psp_types::{
         lsp_types::{
-            request::Initialize, Command, DocumentFilter, DocumentSelector, InitializeParams,
-            MessageType, Url,
+            notification::{DidChangeConfiguration, DidOpenTextDocument, Exit, Progress},
+            request::{ExecuteCommand, Initialize, Shutdown, WorkspaceConfiguration},
+            ConfigurationParams, DidChangeConfigurationParams, DidOpenTextDocumentParams,
+            ExecuteCommandParams, InitializeParams, InitializeResult, MessageType, ProgressParams,
+            Url,
         },
-        Request,
+        Notification, Request,
     },
-    register_plugin, Http, LapcePlugin, VoltEnvironment, PLUGIN_RPC,
+    register_plugin, LapcePlugin, VoltEnvironment, PLUGIN_RPC,
 };
-use regex::Regex::new as Regexp;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::env::var as env_var;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+
+use build_tool::{BloopConfig, BuildServer, BuildTool};
+use github::{get_latest_release_for, ReleaseCheck};
+use jdk::{JdkConfig, JdkPackage, Os, Platform};
+use launch::{host_path, JavaLaunch, LaunchOptions, TransportConfig};
+use logging::LogConfig;
+use metals::{Channel, LaunchMode, MetalsConfig, Resolution};
+use options::{BaseOptions, CompletionConfig, Detected, InlayHintsConfig, SharedSettingsConfig};
+use progress::{MetalsStatusParams, Progress as ProgressItem, ServerStatus, METALS_STATUS};
+use proxy::ProxySettings;
+use runner::{CommandRunner, SystemRunner};
+use sbt::SbtVersions;
+use scala::VersionConfig;
+use selector::{document_selector, ScFiles, SelectorConfig};
+use server::{RestartConfig, Restarts, Server};
+use std::time::Instant;
+use version::Version;
 
 #[derive(Default)]
-struct State {}
+struct State {
+    server: Option<Server>,
+    workspace_root: Option<PathBuf>,
+    workspace_folders: Vec<PathBuf>,
+    initialization_options: Option<Value>,
+    restarts: Restarts,
+    // when the restart server_failed scheduled is due
+    restart_at: Option<Instant>,
+    // the Scala file opened last, what commands on "the current file" act on
+    active_document: Option<Url>,
+    status: ServerStatus,
+}
 
 register_plugin!(State);
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl State {
+    // settings edited while Metals runs are merged into the ones it was
+    // started with and pushed to it, no restart needed. One changed to
+    // null or "" was cleared, it's dropped from both
+    fn change_configuration(&mut self, settings: &Value) {
+        options::apply_changes(
+            self.initialization_options.get_or_insert(Value::Null),
+            settings,
+        );
+        match LogConfig::from_options(self.initialization_options.as_ref()) {
+            Ok(config) => logging::set_level(config.log_level),
+            Err(e) => logging::warn(format!("{e:#}")),
+        }
+        if let Some(server) = &mut self.server {
+            // rebuilt rather than changed in place, a setting the user
+            // cleared goes back to what the plugin detected
+            let options = match &server.base_options {
+                Some(base) => base.with_user(self.initialization_options.as_ref()),
+                None => Ok(self.initialization_options.clone()),
+            };
+            match options {
+                Ok(options) => server.options = options,
+                Err(e) => {
+                    logging::warn(format!("{e:#}, Metals keeps its settings"));
+                    return;
+                }
+            }
+            server::host_notification(
+                DidChangeConfiguration::METHOD,
+                serde_json::json!({ "settings": server.options }),
+            );
+        }
+    }
+
+    // stop Metals so its JVM doesn't outlive the editor. Safe to call
+    // again, `exit` usually follows `shutdown`. Downloads run inside the
+    // request that needs them, so none can be in flight here
+    fn shutdown(&mut self) {
+        if let Some(server) = self.server.take() {
+            if let Err(e) = server.stop() {
+                logging::debug(format!("Metals didn't shut down cleanly: {e:#}"));
+            }
+        }
+        if let Err(e) = jdk::remove_archives(Path::new("jdk")) {
+            logging::debug(format!("couldn't remove JDK archives: {e:#}"));
+        }
+    }
+
+    // the host doesn't tell plugins when a server exits, a crashed Metals
+    // shows up as our own requests to it failing, one that dies while
+    // nothing asks it anything goes unnoticed. It's started again unless it
+    // was shut down on purpose, which drops the server
+    fn server_failed(&mut self, error: &anyhow::Error) {
+        // already on its way back
+        if self.server.is_none() || self.restart_at.is_some() {
+            return;
+        }
+        let max = RestartConfig::from_options(self.initialization_options.as_ref())
+            .unwrap_or_else(|e| {
+                logging::warn(format!("{e:#}, using the default"));
+                RestartConfig::default()
+            })
+            .max_restarts;
+        match self.restarts.next_backoff(max) {
+            Some(backoff) => {
+                PLUGIN_RPC.window_show_message(
+                    MessageType::WARNING,
+                    format!(
+                        "Metals stopped responding ({error:#}), restarting it in {}s ({} of {max})",
+                        backoff.as_secs(),
+                        self.restarts.attempts
+                    ),
+                );
+                self.restart_at = Some(Instant::now() + backoff);
+            }
+            None => PLUGIN_RPC.window_show_message(
+                MessageType::ERROR,
+                format!(
+                    "Metals keeps crashing, gave up after {max} restarts. \
+                     Restart it with Scala: Restart Metals and run Scala: Run Metals Doctor"
+                ),
+            ),
+        }
+    }
+
+    // waiting out the backoff would block the host's messages to us and a
+    // WASI plugin has no timers, so the restart happens with the first
+    // message that arrives once it's due
+    fn restart_if_due(&mut self) {
+        if self.restart_at.is_some_and(|at| Instant::now() >= at) {
+            self.restart_at = None;
+            if let Some(server) = &self.server {
+                server.start();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_change_configuration() {
+    let mut state = State {
+        initialization_options: Some(serde_json::json!({
+            "metals": { "showInferredType": false },
+            "jdkVendor": "temurin",
+        })),
+        ..State::default()
+    };
+    state.change_configuration(&serde_json::json!({
+        "metals": { "showInferredType": true, "javaHome": "" },
+    }));
+    assert_eq!(
+        state.initialization_options,
+        Some(serde_json::json!({
+            "metals": { "showInferredType": true },
+            "jdkVendor": "temurin",
+        }))
+    );
+
+    // cleared in the settings, so no longer the user's
+    state.change_configuration(&serde_json::json!({
+        "metals": { "showInferredType": null },
+        "jdkVendor": "",
+    }));
+    assert_eq!(
+        state.initialization_options,
+        Some(serde_json::json!({ "metals": {} }))
+    );
+
+    let mut state = State::default();
+    state.change_configuration(&serde_json::json!({ "jdkVersion": "17" }));
+    assert_eq!(
+        state.initialization_options,
+        Some(serde_json::json!({ "jdkVersion": "17" }))
+    );
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct PluginInfo {
-    java_version: String,
-    scala_version: String,
+    java_version: Option<String>,
+    scala_version: Option<String>,
     // project and system sbt version
-    sbt_version: Vec<String, String>,
+    sbt_version: SbtVersions,
+    mill_version: Option<String>,
+    scala_cli_version: Option<String>,
+    gradle_version: Option<String>,
+    bloop_version: Option<String>,
+}
+
+impl PluginInfo {
+    // same detection initialize goes through, run again so the report
+    // reflects the workspace as it is now
+    fn detect(
+        workspace_root: Option<&Path>,
+        version_config: &VersionConfig,
+        runner: &dyn CommandRunner,
+    ) -> PluginInfo {
+        let gradle_build = workspace_root
+            .map(build_tool::detect)
+            .is_some_and(|tools| tools.contains(&BuildTool::Gradle));
+        PluginInfo {
+            java_version: jdk::path_java_version(runner),
+            scala_version: scala::detect(workspace_root, version_config, runner),
+            sbt_version: sbt::versions(workspace_root, runner),
+            mill_version: workspace_root.and_then(build_tool::mill_version),
+            scala_cli_version: build_tool::scala_cli_version(runner),
+            // it takes a JVM start, only worth it in Gradle builds
+            gradle_version: gradle_build
+                .then(|| build_tool::gradle_version(runner))
+                .flatten(),
+            bloop_version: build_tool::bloop_version(workspace_root, runner),
+        }
+    }
+
+    // what was detected last time the workspace was opened, as long as
+    // none of its build files changed since. Each detection starts a JVM
+    fn cached(
+        workspace_root: Option<&Path>,
+        version_config: &VersionConfig,
+        runner: &dyn CommandRunner,
+    ) -> PluginInfo {
+        let cache = Path::new(info_cache::CACHE_FILE);
+        if let Some(info) = workspace_root
+            .and_then(|root| info_cache::load(cache, root, version_config, info_cache::now()))
+        {
+            logging::debug("using the versions detected last time");
+            return info;
+        }
+        let info = PluginInfo::detect(workspace_root, version_config, runner);
+        info.remember(workspace_root, version_config);
+        info
+    }
+
+    fn remember(&self, workspace_root: Option<&Path>, version_config: &VersionConfig) {
+        let Some(root) = workspace_root else {
+            return;
+        };
+        let cache = Path::new(info_cache::CACHE_FILE);
+        if let Err(e) = info_cache::store(cache, root, version_config, self) {
+            logging::debug(format!("couldn't cache the detected versions: {e:#}"));
+        }
+    }
+
+    fn report(&self) -> String {
+        let unknown = || "unknown".to_string();
+        let java = self
+            .java_version
+            .clone()
+            .unwrap_or_else(|| "not found on PATH".to_string());
+        format!(
+            "scala-lapce {}\njava: {java}\nscala: {}\nscala-cli: {}\nmill: {}\ngradle: {}\nbloop: {}\nsbt (project): {}\nsbt (system): {}",
+            env!("CARGO_PKG_VERSION"),
+            self.scala_version.clone().unwrap_or_else(unknown),
+            self.scala_cli_version.clone().unwrap_or_else(unknown),
+            self.mill_version.clone().unwrap_or_else(unknown),
+            self.gradle_version.clone().unwrap_or_else(unknown),
+            self.bloop_version.clone().unwrap_or_else(unknown),
+            self.sbt_version.project.clone().unwrap_or_else(unknown),
+            self.sbt_version.system.clone().unwrap_or_else(unknown),
+        )
+    }
+}
+
+#[test]
+fn test_plugin_info_report() {
+    let info = PluginInfo {
+        java_version: Some("21.0.2".to_string()),
+        scala_version: Some("3.3.1".to_string()),
+        sbt_version: SbtVersions {
+            system: None,
+            project: Some("1.9.9".to_string()),
+        },
+        mill_version: None,
+        scala_cli_version: Some("1.1.2".to_string()),
+        gradle_version: Some("8.5".to_string()),
+        bloop_version: Some("1.5.11".to_string()),
+    };
+    let report = info.report();
+    assert!(report.contains("java: 21.0.2\n"));
+    assert!(report.contains("scala: 3.3.1\n"));
+    assert!(report.contains("scala-cli: 1.1.2\n"));
+    assert!(report.contains("mill: unknown\n"));
+    assert!(report.contains("gradle: 8.5\n"));
+    assert!(report.contains("bloop: 1.5.11\n"));
+    assert!(report.contains("sbt (project): 1.9.9\n"));
+    assert!(report.ends_with("sbt (system): unknown"));
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,9 +330,33 @@ pub struct Configuration {
     options: Option<Value>,
 }
 
-register_plugin!(State);
+// where to look for build files. Workspace folders win over the older
+// rootUri and rootPath, which hosts still fill in for the first folder.
+// Metals itself gets its roots from the initialize request Lapce sends it
+fn workspace_folders(params: &InitializeParams) -> Vec<PathBuf> {
+    #[allow(deprecated)]
+    let (root_uri, root_path) = (params.root_uri.as_ref(), params.root_path.as_ref());
+    let mut folders: Vec<PathBuf> = vec![];
+    let paths = params
+        .workspace_folders
+        .iter()
+        .flatten()
+        .map(|folder| &folder.uri)
+        .chain(root_uri)
+        .filter_map(|uri| uri.to_file_path().ok())
+        .chain(root_path.map(PathBuf::from));
+    for path in paths {
+        if !folders.contains(&path) {
+            folders.push(path);
+        }
+    }
+    folders
+}
 
-fn initialize(params: InitializeParams) -> Result<()> {
+fn initialize(params: InitializeParams, workspace_root: Option<&Path>) -> Result<Option<Server>> {
+    logging::set_level(LogConfig::from_options(params.initialization_options.as_ref())?.log_level);
+    TransportConfig::from_options(params.initialization_options.as_ref())?.check()?;
+    let mut selector_config = SelectorConfig::from_options(params.initialization_options.as_ref())?;
     let server_path = params
         .initialization_options
         .as_ref()
@@ -58,96 +371,133 @@ fn initialize(params: InitializeParams) -> Result<()> {
         });
 
     if let Some(server_path) = server_path {
-        PLUGIN_RPC.start_lsp(
-            rl::parse(&format!("urn:{}", server_path))?,
-            vec![],
-            vec![DocumentFilter {
-                language: Some("scala".to_string()),
-                scheme: None,
-                pattern: Some(
-                    "**/*.
-                {scala}"
-                        .to_string(),
+        let server = Server {
+            server_uri: launch::server_uri(server_path)?,
+            server_args: vec![],
+            document_selector: document_selector(&selector_config),
+            options: params.initialization_options,
+            base_options: None,
+            installs: vec![],
+            metals_version: None,
+        };
+        server.start();
+        return Ok(Some(server));
+    }
+
+    // a configured javaHome settles which JDK to use, no need to look further
+    let jdk_config = JdkConfig::from_options(params.initialization_options.as_ref())?;
+    let configured_java_home = jdk_config.configured_java_home();
+    let runner = SystemRunner;
+    let minimum_jdk = jdk::SUPPORTED_JDK_VERSIONS[0];
+    if let Some(home) = configured_java_home {
+        jdk::check_java_home(home)?;
+        // Metals would only fail later with a far less telling error
+        if let Some(version) = jdk::home_java_version(Path::new(home), &runner)
+            .filter(|version| !jdk::is_supported_java(version))
+        {
+            return Err(anyhow!(
+                "javaHome {home} is Java {version}, Metals needs JDK {minimum_jdk}+"
+            ));
+        }
+    }
+    let version_config = VersionConfig::from_options(params.initialization_options.as_ref())?;
+    let info = PluginInfo::cached(workspace_root, &version_config, &runner);
+    let java_version = match configured_java_home {
+        Some(_) => None,
+        None => info.java_version,
+    };
+    // the last resort, so scratch files outside of any build still get Metals
+    let scala_version = info.scala_version.or_else(|| {
+        let fallback = version_config.fallback();
+        logging::warn(format!(
+            "no Scala version found in the workspace or on PATH, using fallbackScalaVersion \
+             {fallback}"
+        ));
+        Some(fallback)
+    });
+    let build_tools = workspace_root.map(build_tool::detect).unwrap_or_default();
+    let build_server = BuildServer::from_options(params.initialization_options.as_ref())
+        .unwrap_or_else(|value| {
+            PLUGIN_RPC.window_show_message(
+                MessageType::WARNING,
+                format!(
+                    "unknown buildServer {value}, expected {}, detecting the build instead",
+                    BuildServer::NAMES
                 ),
-            }],
-            params.initialization_options,
-        );
-        return Ok(());
+            );
+            BuildServer::Auto
+        });
+    // a build server that already left a connection file beats guessing
+    // from the build files
+    let discovered_bsp = workspace_root.and_then(|root| {
+        build_tool::preferred_bsp(&build_tool::bsp_connections(root), &build_tools)
+    });
+    let default_bsp = build_server
+        .bsp_name()
+        .or(discovered_bsp.as_deref())
+        .or_else(|| build_tool::default_bsp(&build_tools));
+    // scala-cli scripts are regular sources, not worksheets
+    if build_tools.contains(&BuildTool::ScalaCli) {
+        selector_config.sc_files = ScFiles::Script;
     }
 
-    let java_version = Command::new(
-        "java_version".to_string(),
-        "java".to_string(),
-        "-version".to_string(),
-    )
-    .output()
-    .map(|output| {
-        String::from_utf8_lossy(&output.stderr)
-            .lines()
-            .next()
-            .unwrap_or_default()
-            .to_string()
-    })
-    .unwrap_or_default();
-
-    // for scala we're actually only interested in the build tag,
-    // primarilfy due to Scala 2 and Scala 3 differences
-    // Therefore we'll trim the output of this command based on regex \d+\.\d+\.\d+
-    let scala_version = Some(
-        Command::new(
-            "scala_version".to_string(),
-            "scala".to_string(),
-            "-version".to_string(),
-        )
-        .output()
-        .map(|output| {
-            let output = String::from_utf8_lossy(&output.stderr)
-                .lines()
-                .next()
-                .unwrap_or_default()
-                .to_string();
-            let re = Regexp(r"\d+\.\d+\.\d+").unwrap();
-            re.find(&output)
-                .map(|m| m.as_str().to_string())
-                .unwrap_or_default()
-        })
-        .unwrap_or_default(),
-    );
+    let metals_config = MetalsConfig::from_options(params.initialization_options.as_ref())?;
+    // only checked, the settings reach Metals with the rest of metals.*
+    BloopConfig::from_options(params.initialization_options.as_ref())?;
+    let custom_repositories = metals_config.repositories()?;
+    if let Some(root) = workspace_root {
+        for missing in options::missing_config_files(params.initialization_options.as_ref(), root) {
+            PLUGIN_RPC.window_show_message(MessageType::WARNING, missing);
+        }
+    }
+    // checked before anything is downloaded, BaseOptions::with_user turns
+    // them into Metals settings
+    InlayHintsConfig::from_options(params.initialization_options.as_ref())?;
+    CompletionConfig::from_options(params.initialization_options.as_ref())?.metals_options()?;
+    let vscode_settings = match workspace_root {
+        Some(root)
+            if SharedSettingsConfig::from_options(params.initialization_options.as_ref())?
+                .import_vscode_metals_settings =>
+        {
+            options::vscode_metals_settings(root).unwrap_or_else(|e| {
+                logging::warn(format!("{e:#}, not importing its Metals settings"));
+                None
+            })
+        }
+        _ => None,
+    };
 
-    // for sbt we'll build a vec of versions for system and project, using the pollowing format:
-    // system: $tag, project: $tag
-    // so that
-    // sbt version in this project: 1.9.9
-    // sbt script version: 1.9.9
-    // would result in ["1.9.9", "1.9.9"]
-    let sbt_version = Command::new(
-        "sbt_version".to_string(),
-        "sbt".to_string(),
-        "-version".to_string(),
-    )
-    .output()
-    .map(|output| {
-        let output = String::from_utf8_lossy(&output.stderr)
-            .lines()
-            .next()
-            .unwrap_or_default()
-            .to_string();
-        let re = Regexp(r"\d+\.\d+\.\d+").unwrap();
-        re.find_iter(&output)
-            .map(|m| m.as_str().to_string())
-            .collect::<Vec<String>>()
+    let base_options = |java_home: Option<&str>| BaseOptions {
+        detected: options::detected(Detected {
+            java_home,
+            scala_version: scala_version.as_deref(),
+            default_bsp,
+            custom_repositories: &custom_repositories,
+            bloop_version: info.bloop_version.as_deref(),
+        }),
+        vscode: vscode_settings.clone(),
+    };
+    // an old JAVA_HOME is passed over, a supported JDK gets downloaded instead
+    let mut java_home = configured_java_home.map(str::to_string).or_else(|| {
+        jdk::system_java_home().filter(|home| {
+            match jdk::home_java_version(Path::new(home), &runner) {
+                Some(version) if !jdk::is_supported_java(&version) => {
+                    PLUGIN_RPC.window_show_message(
+                        MessageType::WARNING,
+                        format!(
+                            "JAVA_HOME {home} is Java {version}, Metals needs JDK \
+                             {minimum_jdk}+, using another JDK"
+                        ),
+                    );
+                    false
+                }
+                _ => true,
+            }
+        })
     });
 
-    let document_selector: DocumentSelector = vec![DocumentFilter {
-        // lsp language id
-        language: Some(String::from("scala")),
-        // glob pattern
-        pattern: Some(String::from("**/*.{scala}")),
-        // like file:
-        scheme: None,
-    }];
+    let document_selector = document_selector(&selector_config);
     let mut server_args = vec![];
-    let mut options = None;
 
     // Check for user specified LSP server path
     // ```
@@ -173,97 +523,380 @@ fn initialize(params: InitializeParams) -> Result<()> {
             if let Some(server_path) = lsp.get("serverPath") {
                 if let Some(server_path) = server_path.as_str() {
                     if !server_path.is_empty() {
-                        let server_uri = Url::parse(&format!("urn:{}", server_path))?;
-                        PLUGIN_RPC.start_lsp(
-                            server_uri,
+                        let base = base_options(java_home.as_deref());
+                        let server = Server {
+                            server_uri: launch::server_uri(server_path)?,
                             server_args,
                             document_selector,
-                            params.initialization_options,
-                        );
-                        return Ok(());
+                            options: base.with_user(params.initialization_options.as_ref())?,
+                            base_options: Some(base),
+                            installs: vec![],
+                            metals_version: None,
+                        };
+                        server.start();
+                        return Ok(Some(server));
                     }
                 }
             }
         }
     }
 
-    // Download URL
-    // let _ = format!("https://github.com/<name>/<project>/releases/download/<version>/{filename}");
+    // from here on the args go to a Metals launcher
+    for arg in metals::unknown_launcher_args(&server_args) {
+        logging::warn(format!(
+            "serverArgs entry {arg:?} isn't a -J, -D or -X flag, Metals may not accept it"
+        ));
+    }
+
+    // the heap and stack sizes an sbt project asks for in .jvmopts and
+    // .sbtopts suit Metals compiling it too
+    let project_options = workspace_root
+        .map(sbt::project_java_options)
+        .unwrap_or_default();
+    if !project_options.is_empty() {
+        logging::debug(format!(
+            "project JVM options: {}",
+            project_options.join(" ")
+        ));
+    }
 
-    // see lapce_plugin::Http for available API to download files
+    // a pre-downloaded launcher skips Coursier and GitHub altogether, for
+    // offline and locked down machines
+    let proxy = ProxySettings::from_env();
+    if let Some(launcher) = metals_config.launcher_path() {
+        metals::check_launcher(Path::new(launcher))?;
+        let mut launcher_args = metals::launcher_java_options(
+            &proxy,
+            &project_options,
+            &metals_config.server_properties,
+        );
+        launcher_args.extend(server_args);
+        // only decides how serverEnv is passed, any unix has `env`
+        let os = VoltEnvironment::operating_system()
+            .ok()
+            .and_then(|os| Os::from_volt(&os))
+            .unwrap_or(Os::Linux);
+        let base = base_options(java_home.as_deref());
+        let mut server = LaunchOptions::new(
+            launch::file_uri(launcher).context("invalid metalsLauncherPath")?,
+            launcher_args,
+        )
+        .with_user_env(&metals_config.server_env)
+        .into_server(
+            os,
+            document_selector,
+            base.with_user(params.initialization_options.as_ref())?,
+        )?;
+        server.base_options = Some(base);
+        server.start();
+        return Ok(Some(server));
+    }
 
-    let latest_jdk_release = get_latest_release_for("adoptium/temurin21-binaries")?;
+    let release_check = ReleaseCheck::from_options(params.initialization_options.as_ref())?;
+    download::set_timeout(release_check.timeout);
+    let latest_jdk_release = jdk_config
+        .jdk_vendor
+        .release_repo(jdk_config.jdk_version)
+        .filter(|_| java_home.is_none() && !jdk_config.disable_auto_jdk_download)
+        .and_then(|repo| {
+            get_latest_release_for(&repo, &release_check)
+                .map_err(|e| logging::warn(format!("JDK release lookup failed: {e:#}")))
+                .ok()
+        });
 
-    let jdk_url = match VoltEnvironment::operating_system().as_deref() {
-        Ok("windows") => {
-            format!("{}.exe", "https://github.com/adoptium/temurin21-binaries/releases/download/jdk-21.0.2%2B13/OpenJDK21U-jdk_x64_windows_hotspot_21.0.2_13.msi")
+    let mut platform = match Platform::detect() {
+        Ok(platform) => platform,
+        Err(e) => {
+            PLUGIN_RPC.window_show_message(
+                MessageType::ERROR,
+                format!("cannot install a JDK for this system: {e}"),
+            );
+            return Ok(None);
         }
-        _ => "[filename]".to_string(),
     };
+    // a glibc JDK crash-loops on Alpine and other musl systems
+    if platform.os == Os::Linux {
+        platform.libc = jdk::detect_libc(VoltEnvironment::libc().ok().as_deref(), &runner);
+    }
 
     // Plugin working directory
-    let volt_uri = VoltEnvironment::uri()?;
-    let server_uri = Url::parse(&volt_uri)?.join("[filename]")?;
+    let volt_uri = Url::parse(&VoltEnvironment::uri()?)?;
+    let metals_version = match metals_config.pinned_version() {
+        Some(version) => {
+            if !metals::looks_like_version(&version) {
+                PLUGIN_RPC.window_show_message(
+                    MessageType::WARNING,
+                    format!(
+                        "metalsVersion {version:?} doesn't look like a Metals release, \
+                         trying it anyway"
+                    ),
+                );
+            }
+            version
+        }
+        None if metals_config.metals_server_version_channel == Channel::Snapshot => {
+            metals::latest_snapshot(scala_version.as_deref())?
+        }
+        None => match get_latest_release_for(metals::METALS_REPO, &release_check) {
+            Ok(version) => version.trim_start_matches('v').to_string(),
+            Err(e) => {
+                PLUGIN_RPC.window_show_message(
+                    MessageType::WARNING,
+                    format!("{e:#}, falling back to Metals {}", metals::METALS_VERSION),
+                );
+                metals::METALS_VERSION.to_string()
+            }
+        },
+    };
+    if let Some(message) = scala_version
+        .as_deref()
+        .and_then(|scala| metals::unsupported_scala(scala, &metals_version))
+    {
+        PLUGIN_RPC.window_show_message(MessageType::WARNING, message);
+    }
+    let resolution = Resolution {
+        repositories: custom_repositories,
+        coursier_repositories: metals_config.coursier_repositories()?,
+        cache: metals::coursier_cache_dir(metals_config.coursier_cache(), platform.os, |name| {
+            env_var(name).ok()
+        }),
+    };
+    let mut installs = vec![];
+    // a configured javaHome is passed on as is, a system JAVA_HOME inherited
+    let mut server_java_home = configured_java_home.map(str::to_string);
+    // Metals looks for javac and the JDK sources in its javaHome, a JRE
+    // has neither, so Metals is only told about a downloaded JDK
+    let mut metals_java_home = true;
+
+    // a working JAVA_HOME is inherited by the server as is, otherwise we
+    // install our own JDK and point the server at it
+    if java_home.is_none() && jdk_config.disable_auto_jdk_download {
+        // the server finds java on PATH by itself
+        if !java_version.as_deref().is_some_and(jdk::is_supported_java) {
+            return Err(anyhow!(
+                "no usable Java found and disableAutoJdkDownload is set, \
+                 set javaHome to a JDK {minimum_jdk}+ installation"
+            ));
+        }
+    } else if java_home.is_none() {
+        match jdk::install(&jdk_config, platform, latest_jdk_release.as_deref()) {
+            Ok(home) => {
+                installs.push(PathBuf::from(&home));
+                let home = host_path(&launch::plugin_uri(&volt_uri, &home)?, platform.os)?;
+                server_java_home = Some(home.clone());
+                // Metals compiles with the same JDK it runs on
+                metals_java_home = jdk_config.jdk_package == JdkPackage::Jdk;
+                java_home = Some(home);
+            }
+            // the server finds java on PATH by itself
+            Err(e) if java_version.as_deref().is_some_and(jdk::is_supported_java) => {
+                PLUGIN_RPC.window_show_message(
+                    MessageType::WARNING,
+                    format!(
+                        "managed JDK unavailable ({e:#}), falling back to java {} on PATH",
+                        java_version.unwrap_or_default()
+                    ),
+                );
+            }
+            Err(e) => {
+                return Err(e.context("no usable JDK found on PATH either"));
+            }
+        }
+    }
 
-    // if you want to use server from PATH
-    // let server_uri = Url::parse(&format!("urn:{filename}"))?;
+    let mut launcher_args =
+        metals::launcher_java_options(&proxy, &project_options, &metals_config.server_properties);
+    launcher_args.extend(server_args);
+    let mut launch = match metals_config.metals_launch_mode {
+        LaunchMode::Launcher => {
+            let launcher = metals::bootstrap(
+                &volt_uri,
+                platform,
+                scala_version.as_deref(),
+                &metals_version,
+                &resolution,
+            )?;
+            let server_uri = launch::plugin_file_uri(&volt_uri, &launcher)?;
+            installs.push(PathBuf::from(&launcher));
+            let mut launch = LaunchOptions::new(server_uri, launcher_args);
+            // the launcher fetches the jars at startup, from the cache it was made with
+            if let Some(cache) = metals_config.coursier_cache() {
+                launch = launch.with_env("COURSIER_CACHE", cache);
+            }
+            launch
+        }
+        // java runs the jars as they are in the Coursier cache
+        LaunchMode::Classpath => {
+            let classpath = metals::fetch_classpath(
+                &volt_uri,
+                platform,
+                scala_version.as_deref(),
+                &metals_version,
+                &resolution,
+            )?;
+            let metals_dir = metals::metals_dir(scala_version.as_deref(), &metals_version);
+            installs.push(PathBuf::from(&metals_dir));
+            let argfile = format!("{metals_dir}/classpath.args");
+            let argfile_host = host_path(&launch::plugin_uri(&volt_uri, &argfile)?, platform.os)?;
+            JavaLaunch::new(
+                launch::java_uri(java_home.as_deref(), platform.os)?,
+                classpath,
+                launch::METALS_MAIN,
+            )
+            .with_jvm_args(launcher_args)
+            .build(platform.os, Path::new(&argfile), &argfile_host)?
+        }
+    };
+    // Metals fetches scalafmt, mdoc and the like with Coursier too
+    if let Some(repositories) = resolution.coursier_repositories_env() {
+        launch = launch.with_env("COURSIER_REPOSITORIES", repositories);
+    }
+    launch = launch.with_user_env(&metals_config.server_env);
+    if let Some(home) = server_java_home {
+        launch = launch.with_env("JAVA_HOME", home);
+    }
 
     // Available language IDs
     // https://github.com/lapce/lapce/blob/HEAD/lapce-proxy/src/buffer.rs#L173
-    PLUGIN_RPC.start_lsp(
-        server_uri,
-        server_args,
+    let base = base_options(java_home.as_deref().filter(|_| metals_java_home));
+    let mut server = launch.into_server(
+        platform.os,
         document_selector,
-        params.initialization_options,
-    );
+        base.with_user(params.initialization_options.as_ref())?,
+    )?;
+    server.base_options = Some(base);
+    server.installs = installs;
+    server.metals_version = Some(metals_version);
+    server.start();
 
-    Ok(())
-}
-
-// WARN: might need to get e.g. latest 100 releases and then filter for the latest stable one
-fn get_latest_release_for(repo: &str) -> Result<String> {
-    let github = GitHub::new().unwrap();
-    let latest_version = github.get_latest_version(repo)?;
-    latest_version
-}
-
-#[test]
-fn test_get_latest_release_for() {
-    // luckily for us the crate for this hasn't been updated in >1yr since commiting this
-    // so is a good test object
-    let repo = "celeo/github_release_check";
-    let latest_version = get_latest_release_for(repo).unwrap();
-    assert_eq!(latest_version, "0.2.1");
+    Ok(Some(server))
 }
 
 // extract major version from release
 // e.g. forr 21.0.2+13, get OpenJDK21U
-fn read_major_jdk_version(release: &str) -> String {
-    let re = Regexp(r"(\d+)\.(\d+)\.(\d+)\+\d+").unwrap();
-    let captures = re.captures(release).unwrap();
-    format!("OpenJDK{}U", captures.get(1).unwrap().as_str())
+fn read_major_jdk_version(release: &str) -> Result<String> {
+    let version = Version::parse(release)
+        .filter(|version| version.build_number().is_some())
+        .filter(|version| matches!(version.pre_release.as_deref(), None | Some("ea")))
+        .ok_or_else(|| anyhow!("unrecognized JDK release {release:?}"))?;
+    Ok(format!("OpenJDK{}U", version.major))
 }
 
 #[test]
 fn test_read_major_jdk_version() {
     let release = "21.0.2+13";
-    let major_version = read_major_jdk_version(release);
+    let major_version = read_major_jdk_version(release).unwrap();
     assert_eq!(major_version, "OpenJDK21U");
+    assert_eq!(read_major_jdk_version("17.0.10+7").unwrap(), "OpenJDK17U");
+    assert_eq!(read_major_jdk_version("22-ea+1").unwrap(), "OpenJDK22U");
+    assert!(read_major_jdk_version("garbage").is_err());
+    assert!(read_major_jdk_version("").is_err());
 }
 
 impl LapcePlugin for State {
-    fn handle_request(&mut self, _id: u64, method: String, params: Value) {
-        #[allow(clippy::single_match)]
+    fn handle_request(&mut self, id: u64, method: String, params: Value) {
+        self.restart_if_due();
         match method.as_str() {
             Initialize::METHOD => {
                 let params: InitializeParams = serde_json::from_value(params).unwrap();
-                if let Err(e) = initialize(params) {
-                    PLUGIN_RPC.window_show_message(
-                        MessageType::ERROR,
-                        format!("plugin returned with error: {e}"),
-                    )
+                self.workspace_folders = workspace_folders(&params);
+                self.workspace_root =
+                    build_tool::workspace_root(&self.workspace_folders).map(Path::to_path_buf);
+                let roots = build_tool::scala_roots(&self.workspace_folders);
+                if roots.len() > 1 {
+                    logging::info(format!(
+                        "{} workspace folders have a build, Metals serves them all",
+                        roots.len()
+                    ));
+                }
+                self.initialization_options = params.initialization_options.clone();
+                // WASI gives us no threads to download on, so the host gets
+                // its answer first and isn't kept waiting on the JDK and
+                // Metals downloads, which then run before we return
+                server::respond(
+                    id,
+                    serde_json::to_value(InitializeResult::default()).unwrap_or_default(),
+                );
+                let preparing = ProgressItem::begin("Preparing Scala language server");
+                match initialize(params, self.workspace_root.as_deref()) {
+                    Ok(server) => self.server = server,
+                    Err(e) => {
+                        logging::error(format!("initialize failed: {e:#}"));
+                        PLUGIN_RPC.window_show_message(
+                            MessageType::ERROR,
+                            format!("plugin returned with error: {e}"),
+                        )
+                    }
+                }
+                preparing.end(None);
+                // registered either way, scala.showPluginInfo helps most when
+                // Metals didn't start. Hosts without dynamic registration
+                // just won't list them
+                if let Err(e) = commands::register() {
+                    logging::debug(format!("couldn't register commands: {e:#}"));
+                }
+            }
+            ExecuteCommand::METHOD => {
+                let params: ExecuteCommandParams = match serde_json::from_value(params) {
+                    Ok(params) => params,
+                    Err(_) => return,
+                };
+                let command = params.command.clone();
+                if let Err(e) = commands::execute(self, params) {
+                    PLUGIN_RPC
+                        .window_show_message(MessageType::ERROR, format!("{command} failed: {e:#}"))
+                }
+            }
+            WorkspaceConfiguration::METHOD => {
+                let params: ConfigurationParams = match serde_json::from_value(params) {
+                    Ok(params) => params,
+                    Err(_) => return,
+                };
+                // what Metals was started with, detected settings included
+                let options = self
+                    .server
+                    .as_ref()
+                    .and_then(|server| server.options.as_ref())
+                    .or(self.initialization_options.as_ref());
+                let items = params
+                    .items
+                    .iter()
+                    .map(|item| options::section(options, item.section.as_deref()))
+                    .collect();
+                server::respond(id, Value::Array(items));
+            }
+            Shutdown::METHOD => {
+                self.shutdown();
+                server::respond(id, Value::Null);
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_notification(&mut self, method: String, params: Value) {
+        self.restart_if_due();
+        match method.as_str() {
+            DidChangeConfiguration::METHOD => {
+                if let Ok(params) = serde_json::from_value::<DidChangeConfigurationParams>(params) {
+                    self.change_configuration(&params.settings);
+                }
+            }
+            DidOpenTextDocument::METHOD => {
+                if let Ok(params) = serde_json::from_value::<DidOpenTextDocumentParams>(params) {
+                    self.active_document = Some(params.text_document.uri);
+                }
+            }
+            Progress::METHOD => {
+                if let Ok(params) = serde_json::from_value::<ProgressParams>(params) {
+                    self.status.progress(params);
+                }
+            }
+            METALS_STATUS => {
+                if let Ok(params) = serde_json::from_value::<MetalsStatusParams>(params) {
+                    self.status.metals_status(&params);
                 }
             }
+            Exit::METHOD => self.shutdown(),
             _ => {}
         }
     }
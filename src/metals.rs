@@ -0,0 +1,1105 @@
+use anyhow::{anyhow, Context, Result};
+use lapce_plugin::psp_types::lsp_types::Url;
+use regex::Regex::new as Regexp;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::jdk::{Arch, Os, Platform};
+use crate::launch::{host_path, plugin_uri};
+use crate::proxy::ProxySettings;
+use crate::runner::{CommandOutput, CommandRunner, SystemRunner};
+use crate::version::Version;
+use crate::{download, extract, logging};
+
+pub const METALS_REPO: &str = "scalameta/metals";
+// used when the latest release can't be looked up
+pub const METALS_VERSION: &str = "1.3.0";
+
+// Sonatype snapshots, where every merge to Metals' main branch is published
+pub const SNAPSHOTS_REPOSITORY: &str = "https://oss.sonatype.org/content/repositories/snapshots";
+
+// where the Metals version comes from when none is pinned
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum Channel {
+    #[default]
+    Stable,
+    Snapshot,
+    Version(String),
+}
+
+impl From<String> for Channel {
+    fn from(channel: String) -> Self {
+        match channel.trim() {
+            "" | "stable" => Channel::Stable,
+            "snapshot" => Channel::Snapshot,
+            version => Channel::Version(version.to_string()),
+        }
+    }
+}
+
+// how Metals is started: through the launcher `cs bootstrap` makes, or by
+// running java on the classpath `cs fetch` resolves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LaunchMode {
+    #[default]
+    Launcher,
+    Classpath,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetalsConfig {
+    // pin a Metals release instead of tracking the latest one
+    #[serde(default)]
+    pub metals_version: Option<String>,
+    #[serde(default)]
+    pub metals_server_version_channel: Channel,
+    // JVM options for the server, e.g. ["-Xss4m", "-Dmetals.verbose=true"]
+    #[serde(default)]
+    pub server_properties: Vec<String>,
+    // a Metals launcher made beforehand, e.g. with `cs bootstrap`
+    #[serde(default)]
+    pub metals_launcher_path: Option<String>,
+    // Maven repositories to resolve Metals from, e.g. an internal Artifactory
+    #[serde(default)]
+    pub custom_repositories: Vec<String>,
+    // extra environment for the server, e.g. COURSIER_CACHE or SBT_OPTS
+    #[serde(default)]
+    pub server_env: BTreeMap<String, String>,
+    // the Coursier cache to look for Metals in and resolve it into
+    #[serde(default)]
+    pub coursier_cache: Option<String>,
+    // Coursier's own default repositories, e.g. a mirror of Maven Central
+    #[serde(default)]
+    pub coursier_repositories: Vec<String>,
+    #[serde(default)]
+    pub metals_launch_mode: LaunchMode,
+}
+
+impl MetalsConfig {
+    pub fn from_options(options: Option<&Value>) -> Result<MetalsConfig> {
+        match options {
+            Some(options) => {
+                serde_json::from_value(options.clone()).context("invalid Metals configuration")
+            }
+            None => Ok(MetalsConfig::default()),
+        }
+    }
+
+    pub fn launcher_path(&self) -> Option<&str> {
+        self.metals_launcher_path
+            .as_deref()
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+    }
+
+    // an explicit metalsVersion wins over the channel
+    pub fn pinned_version(&self) -> Option<String> {
+        match (&self.metals_version, &self.metals_server_version_channel) {
+            (Some(version), _) if !version.is_empty() => Some(version.clone()),
+            (_, Channel::Version(version)) => Some(version.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn coursier_cache(&self) -> Option<&str> {
+        self.coursier_cache
+            .as_deref()
+            .map(str::trim)
+            .filter(|cache| !cache.is_empty())
+    }
+
+    // customRepositories without the blank entries, each checked to be a URL
+    pub fn repositories(&self) -> Result<Vec<String>> {
+        self.custom_repositories
+            .iter()
+            .map(|repository| repository.trim())
+            .filter(|repository| !repository.is_empty())
+            .map(|repository| {
+                Url::parse(repository)
+                    .map(|_| repository.to_string())
+                    .with_context(|| format!("customRepositories entry {repository:?} isn't a URL"))
+            })
+            .collect()
+    }
+
+    // coursierRepositories without the blank entries, each a URL, an
+    // `ivy:` URL pattern or one of Coursier's shorthands like `central`,
+    // `ivy2Local` or `sonatype:snapshots`
+    pub fn coursier_repositories(&self) -> Result<Vec<String>> {
+        let shorthand = Regexp(r"^[A-Za-z][A-Za-z0-9-]*(:[A-Za-z0-9._-]+)?$")?;
+        self.coursier_repositories
+            .iter()
+            .map(|repository| repository.trim())
+            .filter(|repository| !repository.is_empty())
+            .map(|repository| {
+                let url = repository.strip_prefix("ivy:").unwrap_or(repository);
+                let valid = shorthand.is_match(repository)
+                    || (url.contains("://") && Url::parse(url).is_ok());
+                // Coursier splits COURSIER_REPOSITORIES on `|`
+                if valid && !repository.contains('|') {
+                    Ok(repository.to_string())
+                } else {
+                    Err(anyhow!(
+                        "coursierRepositories entry {repository:?} is neither a URL nor a \
+                         Coursier repository like central or sonatype:snapshots"
+                    ))
+                }
+            })
+            .collect()
+    }
+}
+
+// same defaults the VS Code extension starts Metals with
+const DEFAULT_SERVER_PROPERTIES: [&str; 2] = ["-Xss4m", "-Xms100m"];
+
+// the part of a JVM flag that decides whether two flags conflict,
+// `-Xmx2g` and `-Xmx4g` both being `-Xmx`, `-Dfoo=1` and `-Dfoo=2` both `-Dfoo`
+fn jvm_flag_key(flag: &str) -> &str {
+    if let Some(option) = flag.strip_prefix("-XX:") {
+        return option
+            .trim_start_matches(['+', '-'])
+            .split('=')
+            .next()
+            .unwrap_or_default();
+    }
+    if flag.starts_with("-D") {
+        return flag.split('=').next().unwrap_or(flag);
+    }
+    for sized in ["-Xmx", "-Xms", "-Xss", "-Xmn"] {
+        if flag.starts_with(sized) {
+            return sized;
+        }
+    }
+    flag
+}
+
+// defaults, proxy settings and the project's own JVM options (`.jvmopts`)
+// merged with the user's serverProperties, the later flag winning whenever
+// two set the same thing, passed to the launcher as `-J` flags
+pub fn launcher_java_options(
+    proxy: &ProxySettings,
+    project_options: &[String],
+    server_properties: &[String],
+) -> Vec<String> {
+    let proxy = proxy.java_properties();
+    let mut options: Vec<&str> = vec![];
+    for flag in DEFAULT_SERVER_PROPERTIES
+        .into_iter()
+        .chain(proxy.iter().map(String::as_str))
+        .chain(project_options.iter().map(String::as_str))
+        .chain(server_properties.iter().map(String::as_str))
+        .map(str::trim)
+        .filter(|flag| !flag.is_empty())
+    {
+        options.retain(|existing| jvm_flag_key(existing) != jvm_flag_key(flag));
+        options.push(flag);
+    }
+    options
+        .into_iter()
+        .map(|flag| format!("-J{flag}"))
+        .collect()
+}
+
+// serverArgs the Metals launcher doesn't take, it only knows -J flags for
+// the JVM and system properties
+pub fn unknown_launcher_args(server_args: &[String]) -> Vec<&str> {
+    server_args
+        .iter()
+        .map(String::as_str)
+        .filter(|arg| !["-J", "-D", "-X"].iter().any(|flag| arg.starts_with(flag)))
+        .collect()
+}
+
+#[test]
+fn test_unknown_launcher_args() {
+    let args = [
+        "-J-Xmx2g".to_string(),
+        "-Dmetals.verbose=true".to_string(),
+        "--verbose".to_string(),
+        "metals.verbose".to_string(),
+    ];
+    assert_eq!(
+        unknown_launcher_args(&args),
+        ["--verbose", "metals.verbose"]
+    );
+}
+
+// newest snapshot listed in the maven metadata of the Sonatype snapshots repo
+pub fn latest_snapshot(scala_version: Option<&str>) -> Result<String> {
+    let url = format!(
+        "{SNAPSHOTS_REPOSITORY}/org/scalameta/{}/maven-metadata.xml",
+        artifact(scala_version)
+    );
+    let metadata =
+        download::fetch_text(&url).context("could not look up the latest Metals snapshot")?;
+    parse_latest_snapshot(&metadata).ok_or_else(|| anyhow!("no Metals snapshot listed in {url}"))
+}
+
+fn parse_latest_snapshot(metadata: &str) -> Option<String> {
+    let re = Regexp(r"<latest>([^<]+)</latest>").ok()?;
+    if let Some(captures) = re.captures(metadata) {
+        return Some(captures[1].trim().to_string());
+    }
+    // not every repository manager fills in <latest>, versions are listed oldest first
+    let re = Regexp(r"<version>([^<]+)</version>").ok()?;
+    re.captures_iter(metadata)
+        .last()
+        .map(|captures| captures[1].trim().to_string())
+}
+
+pub fn is_snapshot(version: &str) -> bool {
+    version.ends_with("-SNAPSHOT")
+}
+
+// loose check, snapshots look like 1.3.0+12-5a8d0b2e-SNAPSHOT
+pub fn looks_like_version(version: &str) -> bool {
+    Regexp(r"^\d+\.\d+\.\d+([-+][0-9A-Za-z.+-]+)?$")
+        .map(|re| re.is_match(version))
+        .unwrap_or(false)
+}
+
+// coursier only publishes x86_64 launchers, ARM ones come from VirtusLab
+fn coursier_url(platform: Platform) -> &'static str {
+    match (platform.os, platform.arch) {
+        (Os::Linux, Arch::X64) => {
+            "https://github.com/coursier/coursier/releases/latest/download/cs-x86_64-pc-linux.gz"
+        }
+        (Os::Linux, Arch::Aarch64) => {
+            "https://github.com/VirtusLab/coursier-m1/releases/latest/download/cs-aarch64-pc-linux.gz"
+        }
+        (Os::Mac, Arch::X64) => {
+            "https://github.com/coursier/coursier/releases/latest/download/cs-x86_64-apple-darwin.gz"
+        }
+        (Os::Mac, Arch::Aarch64) => {
+            "https://github.com/VirtusLab/coursier-m1/releases/latest/download/cs-aarch64-apple-darwin.gz"
+        }
+        (Os::Windows, _) => {
+            "https://github.com/coursier/coursier/releases/latest/download/cs-x86_64-pc-win32.zip"
+        }
+    }
+}
+
+// fetch the native coursier launcher into `coursier/` unless we already have it
+fn coursier(volt_uri: &Url, platform: Platform) -> Result<PathBuf> {
+    let dir = Path::new("coursier");
+    let cs = match platform.os {
+        Os::Windows => dir.join("cs.exe"),
+        _ => dir.join("cs"),
+    };
+    if cs.exists() {
+        return Ok(cs);
+    }
+
+    fs::create_dir_all(dir)?;
+    let url = coursier_url(platform);
+    let archive = dir.join(url.rsplit('/').next().unwrap_or_default());
+    download::fetch(url, &archive)?;
+    match platform.os {
+        Os::Windows => {
+            extract::unzip(&archive, dir)?;
+            fs::rename(dir.join("cs-x86_64-pc-win32.exe"), &cs)?;
+        }
+        _ => {
+            extract::gunzip(&archive, &cs)?;
+            make_executable(volt_uri, platform, &cs)?;
+        }
+    }
+    fs::remove_file(&archive)?;
+    Ok(cs)
+}
+
+// WASI can't set permission bits, files we write come out 0644 on the
+// host, so the host's chmod does it
+fn make_executable(volt_uri: &Url, platform: Platform, path: &Path) -> Result<()> {
+    let host = coursier_host_path(volt_uri, platform, &path.to_string_lossy())?;
+    let output = SystemRunner
+        .run("chmod", &["+x", &host])
+        .context("failed to run chmod")?;
+    if !output.success {
+        return Err(anyhow!("chmod +x {host} failed: {}", output.stderr.trim()));
+    }
+    Ok(())
+}
+
+// fail early on a mistyped metalsLauncherPath rather than with whatever
+// the host makes of a server that can't be spawned
+pub fn check_launcher(path: &Path) -> Result<()> {
+    let metadata = fs::metadata(path)
+        .with_context(|| format!("metalsLauncherPath {} doesn't exist", path.display()))?;
+    if !metadata.is_file() || !is_executable(&metadata) {
+        return Err(anyhow!(
+            "metalsLauncherPath {} isn't an executable file",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+// no permission bits to go by, anything that's there may be runnable
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    true
+}
+
+#[test]
+fn test_check_launcher() {
+    let dir = std::env::temp_dir().join("scala-lapce-test-check-launcher");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    assert!(check_launcher(&dir.join("metals")).is_err());
+    assert!(check_launcher(&dir).is_err());
+
+    let launcher = dir.join("metals");
+    fs::write(&launcher, "#!/bin/sh\n").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&launcher, fs::Permissions::from_mode(0o755)).unwrap();
+        check_launcher(&launcher).unwrap();
+        fs::set_permissions(&launcher, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(check_launcher(&launcher).is_err());
+    }
+    #[cfg(not(unix))]
+    check_launcher(&launcher).unwrap();
+}
+
+// the Metals artifact, whatever Scala the project is on. The server's own
+// Scala version is unrelated to the project's: Metals 1.x is only published
+// as metals_2.13, and that build serves 2.12, 2.13 and Scala 3 projects
+pub fn artifact(_scala_version: Option<&str>) -> &'static str {
+    "metals_2.13"
+}
+
+// Scala versions Metals dropped, with the last Metals release that still
+// supports them. 2.10 never was
+const DROPPED_SCALA: [(&str, Option<&str>); 2] = [("2.10.", None), ("2.11.", Some("0.11.12"))];
+
+// a message for when Metals `version` can't serve a project on Scala
+// `scala_version`, naming the Metals release to pin instead when there is one
+pub fn unsupported_scala(scala_version: &str, version: &str) -> Option<String> {
+    let (_, last) = DROPPED_SCALA
+        .iter()
+        .find(|(series, _)| scala_version.starts_with(series))?;
+    match last {
+        Some(last) => {
+            let newer = Version::parse(version)? > Version::parse(last)?;
+            newer.then(|| {
+                format!(
+                    "Metals {version} doesn't support Scala {scala_version}, set metalsVersion \
+                     to {last}, the last release that does, or upgrade the build"
+                )
+            })
+        }
+        None => Some(format!(
+            "no Metals release supports Scala {scala_version}, upgrade the build to 2.12 or \
+             later for code navigation and completions"
+        )),
+    }
+}
+
+#[test]
+fn test_unsupported_scala() {
+    assert!(unsupported_scala("2.11.12", "1.3.0")
+        .unwrap()
+        .contains("set metalsVersion to 0.11.12"));
+    assert_eq!(unsupported_scala("2.11.12", "0.11.12"), None);
+    assert!(unsupported_scala("2.11.12", "1.3.0+12-5a8d0b2e-SNAPSHOT").is_some());
+    assert!(unsupported_scala("2.10.7", "0.11.12").is_some());
+    assert_eq!(unsupported_scala("2.12.18", "1.3.0"), None);
+    assert_eq!(unsupported_scala("2.13.12", "1.3.0"), None);
+    assert_eq!(unsupported_scala("3.3.1", "1.3.0"), None);
+    assert_eq!(unsupported_scala("3.4.0", "1.3.0"), None);
+}
+
+pub fn coordinate(scala_version: Option<&str>, version: &str) -> String {
+    format!("org.scalameta:{}:{version}", artifact(scala_version))
+}
+
+// Maven Central, where Coursier resolves from without customRepositories
+const CENTRAL_REPOSITORY: &str = "https://repo1.maven.org/maven2";
+
+// where coursier resolves Metals from and into
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Resolution {
+    // customRepositories, in place of Coursier's defaults
+    pub repositories: Vec<String>,
+    // coursierRepositories, in place of Coursier's defaults while resolving
+    // and passed to the server as COURSIER_REPOSITORIES
+    pub coursier_repositories: Vec<String>,
+    // on the host
+    pub cache: Option<PathBuf>,
+}
+
+impl Resolution {
+    // Coursier reads its default repositories from the environment joined
+    // with `|`, e.g. `ivy2Local|https://nexus.example.com/maven-central`
+    pub fn coursier_repositories_env(&self) -> Option<String> {
+        (!self.coursier_repositories.is_empty()).then(|| self.coursier_repositories.join("|"))
+    }
+
+    // the repositories Metals may be cached from. Only URLs and `central`
+    // are looked up, other shorthands just mean resolving online
+    fn cached_repositories(&self) -> Vec<String> {
+        if !self.repositories.is_empty() {
+            return self.repositories.clone();
+        }
+        if self.coursier_repositories.is_empty() {
+            return vec![CENTRAL_REPOSITORY.to_string()];
+        }
+        self.coursier_repositories
+            .iter()
+            .filter_map(|repository| match repository.as_str() {
+                "central" => Some(CENTRAL_REPOSITORY.to_string()),
+                url if url.contains("://") => Some(url.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn is_cached(&self, scala_version: Option<&str>, version: &str) -> bool {
+        self.cache.as_deref().is_some_and(|cache| {
+            in_coursier_cache(cache, &self.cached_repositories(), scala_version, version)
+        })
+    }
+
+    // the coursier flags resolving from here. The host runs coursier
+    // without our environment, coursierRepositories are passed the way
+    // customRepositories are, in place of Coursier's defaults
+    fn args(&self, version: &str) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(cache) = &self.cache {
+            args.extend(["--cache".to_string(), cache.to_string_lossy().into_owned()]);
+        }
+        // custom repositories replace Coursier's defaults, so machines that
+        // can't reach Maven Central don't wait on it
+        let repositories = if self.repositories.is_empty() {
+            &self.coursier_repositories
+        } else {
+            &self.repositories
+        };
+        if !repositories.is_empty() {
+            args.push("--no-default".to_string());
+        }
+        for repository in repositories {
+            args.extend(["-r".to_string(), repository.clone()]);
+        }
+        if is_snapshot(version) {
+            args.extend(["-r".to_string(), SNAPSHOTS_REPOSITORY.to_string()]);
+        }
+        args
+    }
+}
+
+// the Coursier cache on the host: coursierCache, then COURSIER_CACHE, then
+// Coursier's own default for the OS
+pub fn coursier_cache_dir(
+    configured: Option<&str>,
+    os: Os,
+    env: impl Fn(&str) -> Option<String>,
+) -> Option<PathBuf> {
+    let env = |name: &str| env(name).filter(|value| !value.is_empty());
+    if let Some(cache) = configured
+        .map(str::to_string)
+        .or_else(|| env("COURSIER_CACHE"))
+    {
+        return Some(PathBuf::from(cache));
+    }
+    let cache = match os {
+        Os::Linux => match env("XDG_CACHE_HOME") {
+            Some(xdg) => PathBuf::from(xdg).join("coursier"),
+            None => PathBuf::from(env("HOME")?).join(".cache/coursier"),
+        },
+        Os::Mac => PathBuf::from(env("HOME")?).join("Library/Caches/Coursier"),
+        Os::Windows => PathBuf::from(env("LOCALAPPDATA")?).join("Coursier/Cache"),
+    };
+    Some(cache.join("v1"))
+}
+
+// where Coursier keeps the Metals jar fetched from `repository`, every URL
+// is cached under its scheme, host and path
+fn cached_jar(
+    cache: &Path,
+    repository: &str,
+    scala_version: Option<&str>,
+    version: &str,
+) -> Option<PathBuf> {
+    let url = Url::parse(repository).ok()?;
+    let artifact = artifact(scala_version);
+    let mut jar = cache.join(url.scheme()).join(url.host_str()?);
+    jar.extend(url.path_segments()?.filter(|segment| !segment.is_empty()));
+    Some(jar.join(format!(
+        "org/scalameta/{artifact}/{version}/{artifact}-{version}.jar"
+    )))
+}
+
+// whether an earlier resolution left the Metals jar from one of the
+// repositories in the cache, so bootstrapping doesn't need the network.
+// Snapshot jars are named by their publish time, those always go through
+// the repositories
+fn in_coursier_cache(
+    cache: &Path,
+    repositories: &[String],
+    scala_version: Option<&str>,
+    version: &str,
+) -> bool {
+    if is_snapshot(version) {
+        return false;
+    }
+    repositories.iter().any(|repository| {
+        cached_jar(cache, repository, scala_version, version).is_some_and(|jar| jar.is_file())
+    })
+}
+
+#[test]
+fn test_coursier_cache_dir() {
+    let env = |vars: &'static [(&'static str, &'static str)]| {
+        move |name: &str| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    };
+    let home = env(&[("HOME", "/home/jane")]);
+    assert_eq!(
+        coursier_cache_dir(None, Os::Linux, home),
+        Some(PathBuf::from("/home/jane/.cache/coursier/v1"))
+    );
+    assert_eq!(
+        coursier_cache_dir(None, Os::Mac, home),
+        Some(PathBuf::from("/home/jane/Library/Caches/Coursier/v1"))
+    );
+    let xdg = env(&[
+        ("HOME", "/home/jane"),
+        ("XDG_CACHE_HOME", "/var/cache/jane"),
+    ]);
+    assert_eq!(
+        coursier_cache_dir(None, Os::Linux, xdg),
+        Some(PathBuf::from("/var/cache/jane/coursier/v1"))
+    );
+    let set = env(&[("HOME", "/home/jane"), ("COURSIER_CACHE", "/srv/coursier")]);
+    assert_eq!(
+        coursier_cache_dir(None, Os::Linux, set),
+        Some(PathBuf::from("/srv/coursier"))
+    );
+    assert_eq!(
+        coursier_cache_dir(Some("/mnt/cache"), Os::Linux, set),
+        Some(PathBuf::from("/mnt/cache"))
+    );
+    assert_eq!(coursier_cache_dir(None, Os::Windows, home), None);
+}
+
+#[test]
+fn test_resolution_args() {
+    let resolution = Resolution {
+        coursier_repositories: vec!["ivy2Local".to_string(), "central".to_string()],
+        cache: Some(PathBuf::from("/srv/coursier")),
+        ..Resolution::default()
+    };
+    assert_eq!(
+        resolution.args("1.3.0"),
+        [
+            "--cache",
+            "/srv/coursier",
+            "--no-default",
+            "-r",
+            "ivy2Local",
+            "-r",
+            "central"
+        ]
+    );
+    let custom = Resolution {
+        repositories: vec!["https://artifactory.example.com/maven-remote".to_string()],
+        ..resolution
+    };
+    assert_eq!(
+        custom.args("1.3.0+12-5a8d0b2e-SNAPSHOT")[2..],
+        [
+            "--no-default",
+            "-r",
+            "https://artifactory.example.com/maven-remote",
+            "-r",
+            SNAPSHOTS_REPOSITORY
+        ]
+    );
+    assert!(Resolution::default().args("1.3.0").is_empty());
+}
+
+#[test]
+fn test_in_coursier_cache() {
+    let cache = std::env::temp_dir().join("scala-lapce-test-coursier-cache");
+    let _ = fs::remove_dir_all(&cache);
+    let jar = cache
+        .join("https/repo1.maven.org/maven2/org/scalameta/metals_2.13/1.3.0/metals_2.13-1.3.0.jar");
+    fs::create_dir_all(jar.parent().unwrap()).unwrap();
+    fs::write(&jar, b"").unwrap();
+
+    let central = Resolution {
+        cache: Some(cache.clone()),
+        ..Resolution::default()
+    };
+    assert!(central.is_cached(Some("3.3.1"), "1.3.0"));
+    assert!(central.is_cached(Some("2.12.18"), "1.3.0"));
+    assert!(!central.is_cached(Some("3.3.1"), "1.2.2"));
+    let mirror = Resolution {
+        coursier_repositories: vec!["ivy2Local".to_string(), "central".to_string()],
+        ..central.clone()
+    };
+    assert!(mirror.is_cached(Some("3.3.1"), "1.3.0"));
+    let local = Resolution {
+        coursier_repositories: vec!["ivy2Local".to_string()],
+        ..central
+    };
+    assert!(!local.is_cached(Some("3.3.1"), "1.3.0"));
+    let artifactory = ["https://artifactory.example.com/maven-remote/".to_string()];
+    assert!(!in_coursier_cache(
+        &cache,
+        &artifactory,
+        Some("3.3.1"),
+        "1.3.0"
+    ));
+    assert_eq!(
+        cached_jar(&cache, &artifactory[0], None, "1.3.0"),
+        Some(cache.join(
+            "https/artifactory.example.com/maven-remote/org/scalameta/metals_2.13/1.3.0/metals_2.13-1.3.0.jar"
+        ))
+    );
+}
+
+// launcher script `cs bootstrap` writes, relative to the plugin working directory
+fn launcher_path(platform: Platform, dir: &str) -> String {
+    match platform.os {
+        Os::Windows => format!("{dir}/metals.bat"),
+        _ => format!("{dir}/metals"),
+    }
+}
+
+// where a Metals build is resolved to, relative to the plugin working
+// directory. Other versions' directories stay, another window may still
+// be running one, and they hold no more than a launcher or a jar listing.
+// scala.clearCache removes them
+pub fn metals_dir(scala_version: Option<&str>, version: &str) -> String {
+    format!("metals/{}-{version}", artifact(scala_version))
+}
+
+// coursier runs on the host, so it needs real paths rather than ones
+// relative to our sandbox
+fn coursier_host_path(volt_uri: &Url, platform: Platform, path: &str) -> Result<String> {
+    host_path(&plugin_uri(volt_uri, path)?, platform.os)
+}
+
+// run a coursier command resolving Metals on the host, `args` starting
+// with the subcommand. Repositories, cache and proxy come on top
+fn run_coursier(
+    volt_uri: &Url,
+    platform: Platform,
+    args: &[String],
+    scala_version: Option<&str>,
+    version: &str,
+    resolution: &Resolution,
+) -> Result<CommandOutput> {
+    let cs = coursier(volt_uri, platform)?;
+    let cs = coursier_host_path(volt_uri, platform, &cs.to_string_lossy())?;
+    let mut args = args.to_vec();
+    args.extend(resolution.args(version));
+    args.extend(
+        ProxySettings::from_env()
+            .java_properties()
+            .iter()
+            .map(|property| format!("-J{property}")),
+    );
+    let run = |args: &[String]| {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        SystemRunner
+            .run(&cs, &args)
+            .context("failed to run coursier")
+    };
+    // Metals resolved before is reused from the cache without asking any
+    // repository, anything missing from it sends us back online
+    let mut output = None;
+    if resolution.is_cached(scala_version, version) {
+        logging::info(format!(
+            "Metals {version} is in the Coursier cache, resolving offline"
+        ));
+        let mut offline_args = args.clone();
+        offline_args.extend(["--mode".to_string(), "offline".to_string()]);
+        let offline = run(&offline_args)?;
+        if offline.success {
+            output = Some(offline);
+        } else {
+            logging::debug(format!(
+                "offline resolution failed, resolving online: {}",
+                offline.stderr.trim()
+            ));
+        }
+    }
+    let output = match output {
+        Some(output) => output,
+        None => run(&args)?,
+    };
+    if !output.success {
+        let stderr = &output.stderr;
+        return Err(if is_snapshot(version) {
+            anyhow!(
+                "coursier could not resolve Metals snapshot {version} from {SNAPSHOTS_REPOSITORY}, \
+                 it may have been cleaned up, pick a newer one or switch back to the stable \
+                 channel: {}",
+                stderr.trim()
+            )
+        } else {
+            anyhow!(
+                "coursier failed to {} Metals {version}: {}",
+                args.first().map_or("resolve", String::as_str),
+                stderr.trim()
+            )
+        });
+    }
+    Ok(output)
+}
+
+// produce a Metals launcher with `cs bootstrap`, reusing the one from a
+// previous session if it's there, and return its path relative to the
+// plugin working directory
+pub fn bootstrap(
+    volt_uri: &Url,
+    platform: Platform,
+    scala_version: Option<&str>,
+    version: &str,
+    resolution: &Resolution,
+) -> Result<String> {
+    let dir = metals_dir(scala_version, version);
+    let launcher = launcher_path(platform, &dir);
+    if Path::new(&launcher).exists() {
+        return Ok(launcher);
+    }
+    let args = [
+        "bootstrap".to_string(),
+        coordinate(scala_version, version),
+        "-o".to_string(),
+        coursier_host_path(volt_uri, platform, &format!("{dir}/metals"))?,
+        "-f".to_string(),
+    ];
+    run_coursier(
+        volt_uri,
+        platform,
+        &args,
+        scala_version,
+        version,
+        resolution,
+    )?;
+    Ok(launcher)
+}
+
+// what `cs fetch --classpath` prints, the jars joined like PATH entries
+fn parse_classpath(output: &str, os: Os) -> Vec<String> {
+    let separator = match os {
+        Os::Windows => ';',
+        _ => ':',
+    };
+    output
+        .trim()
+        .split(separator)
+        .map(str::trim)
+        .filter(|jar| !jar.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn test_parse_classpath() {
+    let linux = "/home/jane/.cache/coursier/v1/https/repo1.maven.org/maven2/org/scalameta/metals_2.13/1.3.0/metals_2.13-1.3.0.jar:\
+                 /home/jane/.cache/coursier/v1/https/repo1.maven.org/maven2/org/scala-lang/scala-library/2.13.12/scala-library-2.13.12.jar\n";
+    assert_eq!(parse_classpath(linux, Os::Linux).len(), 2);
+    let windows = "C:\\Users\\Jane\\AppData\\Local\\Coursier\\Cache\\v1\\metals_2.13-1.3.0.jar;\
+                   C:\\Users\\Jane\\AppData\\Local\\Coursier\\Cache\\v1\\scala-library-2.13.12.jar\r\n";
+    assert_eq!(
+        parse_classpath(windows, Os::Windows),
+        [
+            "C:\\Users\\Jane\\AppData\\Local\\Coursier\\Cache\\v1\\metals_2.13-1.3.0.jar",
+            "C:\\Users\\Jane\\AppData\\Local\\Coursier\\Cache\\v1\\scala-library-2.13.12.jar"
+        ]
+    );
+    assert!(parse_classpath("", Os::Linux).is_empty());
+}
+
+// the jars Metals runs from, as host paths, resolved with `cs fetch`.
+// They're listed in the Metals directory so later sessions only resolve
+// again once a jar has gone from the cache
+pub fn fetch_classpath(
+    volt_uri: &Url,
+    platform: Platform,
+    scala_version: Option<&str>,
+    version: &str,
+    resolution: &Resolution,
+) -> Result<Vec<String>> {
+    let dir = metals_dir(scala_version, version);
+    let listed = Path::new(&dir).join("classpath.txt");
+    if let Ok(contents) = fs::read_to_string(&listed) {
+        let classpath: Vec<String> = contents.lines().map(str::to_string).collect();
+        if !classpath.is_empty() && classpath.iter().all(|jar| Path::new(jar).is_file()) {
+            return Ok(classpath);
+        }
+    }
+    let args = [
+        "fetch".to_string(),
+        coordinate(scala_version, version),
+        "--classpath".to_string(),
+    ];
+    let output = run_coursier(
+        volt_uri,
+        platform,
+        &args,
+        scala_version,
+        version,
+        resolution,
+    )?;
+    let classpath = parse_classpath(&output.stdout, platform.os);
+    if classpath.is_empty() {
+        return Err(anyhow!("coursier fetched no jars for Metals {version}"));
+    }
+    fs::create_dir_all(&dir)?;
+    fs::write(&listed, classpath.join("\n"))?;
+    Ok(classpath)
+}
+
+#[test]
+fn test_launcher_path() {
+    let linux = Platform::from_volt("linux", "x86_64").unwrap();
+    let windows = Platform::from_volt("windows", "x86_64").unwrap();
+    assert_eq!(
+        launcher_path(linux, "metals/metals_2.13-1.3.0"),
+        "metals/metals_2.13-1.3.0/metals"
+    );
+    assert_eq!(
+        launcher_path(windows, "metals/metals_2.13-1.3.0"),
+        "metals/metals_2.13-1.3.0/metals.bat"
+    );
+}
+
+#[test]
+fn test_coordinate() {
+    assert_eq!(
+        coordinate(Some("2.12.18"), "1.3.0"),
+        "org.scalameta:metals_2.13:1.3.0"
+    );
+    assert_eq!(
+        coordinate(Some("2.13.12"), "1.3.0"),
+        "org.scalameta:metals_2.13:1.3.0"
+    );
+    assert_eq!(
+        coordinate(Some("3.3.1"), "1.3.0"),
+        "org.scalameta:metals_2.13:1.3.0"
+    );
+    assert_eq!(coordinate(None, "1.3.0"), "org.scalameta:metals_2.13:1.3.0");
+}
+
+#[test]
+fn test_metals_config() {
+    let config =
+        MetalsConfig::from_options(Some(&serde_json::json!({ "metalsVersion": "1.2.2" }))).unwrap();
+    assert_eq!(config.metals_version.as_deref(), Some("1.2.2"));
+    assert_eq!(
+        MetalsConfig::from_options(None).unwrap().metals_version,
+        None
+    );
+
+    let launcher = |path: &str| {
+        MetalsConfig::from_options(Some(&serde_json::json!({ "metalsLauncherPath": path })))
+            .unwrap()
+            .launcher_path()
+            .map(str::to_string)
+    };
+    assert_eq!(
+        launcher("/opt/metals/bin/metals").as_deref(),
+        Some("/opt/metals/bin/metals")
+    );
+    assert_eq!(launcher(""), None);
+
+    let repositories = |repositories: Value| {
+        MetalsConfig::from_options(Some(&serde_json::json!({
+            "customRepositories": repositories,
+        })))
+        .unwrap()
+        .repositories()
+    };
+    assert_eq!(
+        repositories(serde_json::json!([
+            " https://artifactory.example.com/maven-remote ",
+            "",
+        ]))
+        .unwrap(),
+        ["https://artifactory.example.com/maven-remote"]
+    );
+    assert!(repositories(serde_json::json!(["artifactory.example.com/maven"])).is_err());
+    assert!(MetalsConfig::default().repositories().unwrap().is_empty());
+
+    let coursier_repositories = |repositories: Value| {
+        MetalsConfig::from_options(Some(&serde_json::json!({
+            "coursierRepositories": repositories,
+        })))
+        .unwrap()
+        .coursier_repositories()
+    };
+    let mirror = Resolution {
+        coursier_repositories: coursier_repositories(serde_json::json!([
+            "ivy2Local",
+            " sonatype:snapshots ",
+            "",
+            "https://nexus.example.com/repository/maven-central",
+            "ivy:https://repo.example.com/ivy/[organisation]/[module]/[revision]/[artifact].[ext]",
+        ]))
+        .unwrap(),
+        ..Resolution::default()
+    };
+    assert_eq!(
+        mirror.coursier_repositories_env().as_deref(),
+        Some(
+            "ivy2Local|sonatype:snapshots|https://nexus.example.com/repository/maven-central|\
+             ivy:https://repo.example.com/ivy/[organisation]/[module]/[revision]/[artifact].[ext]"
+        )
+    );
+    assert_eq!(Resolution::default().coursier_repositories_env(), None);
+    for invalid in [
+        "central|ivy2Local",
+        "nexus.example.com/maven",
+        "maven central",
+    ] {
+        assert!(
+            coursier_repositories(serde_json::json!([invalid])).is_err(),
+            "{invalid:?}"
+        );
+    }
+
+    let config = MetalsConfig::from_options(Some(&serde_json::json!({
+        "serverEnv": { "COURSIER_CACHE": "/srv/coursier" },
+    })))
+    .unwrap();
+    assert_eq!(
+        config.server_env.get("COURSIER_CACHE").map(String::as_str),
+        Some("/srv/coursier")
+    );
+}
+
+#[test]
+fn test_looks_like_version() {
+    assert!(looks_like_version("1.3.0"));
+    assert!(looks_like_version("1.3.0+12-5a8d0b2e-SNAPSHOT"));
+    assert!(looks_like_version("1.0.0-RC1"));
+    assert!(!looks_like_version("latest"));
+    assert!(!looks_like_version("v1.3.0"));
+    assert!(!looks_like_version("1.3"));
+}
+
+#[test]
+fn test_channel() {
+    let channel = |value: &str| {
+        MetalsConfig::from_options(Some(&serde_json::json!({
+            "metalsServerVersionChannel": value,
+        })))
+        .unwrap()
+        .metals_server_version_channel
+    };
+    assert_eq!(channel("stable"), Channel::Stable);
+    assert_eq!(channel(""), Channel::Stable);
+    assert_eq!(channel("snapshot"), Channel::Snapshot);
+    assert_eq!(channel("1.2.2"), Channel::Version("1.2.2".to_string()));
+    assert_eq!(
+        MetalsConfig::default().metals_server_version_channel,
+        Channel::Stable
+    );
+}
+
+#[test]
+fn test_launcher_java_options() {
+    let properties = ["-Xss8m", "-Dmetals.verbose=true", "-Xmx2g", "-Xmx4g"].map(String::from);
+    assert_eq!(
+        launcher_java_options(&ProxySettings::default(), &[], &properties),
+        [
+            "-J-Xms100m",
+            "-J-Xss8m",
+            "-J-Dmetals.verbose=true",
+            "-J-Xmx4g"
+        ]
+    );
+    assert_eq!(
+        launcher_java_options(&ProxySettings::default(), &[], &[]),
+        ["-J-Xss4m", "-J-Xms100m"]
+    );
+    let properties = ["-XX:+UseG1GC", "-XX:-UseG1GC", "-Dmetals.verbose=false"].map(String::from);
+    assert_eq!(
+        launcher_java_options(&ProxySettings::default(), &[], &properties),
+        [
+            "-J-Xss4m",
+            "-J-Xms100m",
+            "-J-XX:-UseG1GC",
+            "-J-Dmetals.verbose=false"
+        ]
+    );
+    // the project's .jvmopts beat the defaults, serverProperties beat both
+    let project = ["-Xss8m", "-Xmx4g"].map(String::from);
+    let properties = ["-Xmx2g".to_string()];
+    assert_eq!(
+        launcher_java_options(&ProxySettings::default(), &project, &properties),
+        ["-J-Xms100m", "-J-Xss8m", "-J-Xmx2g"]
+    );
+}
+
+#[test]
+fn test_pinned_version() {
+    let config = MetalsConfig {
+        metals_version: Some("1.2.2".to_string()),
+        metals_server_version_channel: Channel::Version("1.1.0".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(config.pinned_version().as_deref(), Some("1.2.2"));
+    let config = MetalsConfig {
+        metals_version: Some(String::new()),
+        metals_server_version_channel: Channel::Version("1.1.0".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(config.pinned_version().as_deref(), Some("1.1.0"));
+    let config = MetalsConfig {
+        metals_version: None,
+        metals_server_version_channel: Channel::Snapshot,
+        ..Default::default()
+    };
+    assert_eq!(config.pinned_version(), None);
+}
+
+#[test]
+fn test_parse_latest_snapshot() {
+    let metadata = r#"<metadata>
+  <groupId>org.scalameta</groupId>
+  <artifactId>metals_2.13</artifactId>
+  <versioning>
+    <latest>1.3.0+56-2bd3d4cb-SNAPSHOT</latest>
+    <versions>
+      <version>1.3.0+55-0f6e3a1d-SNAPSHOT</version>
+      <version>1.3.0+56-2bd3d4cb-SNAPSHOT</version>
+    </versions>
+  </versioning>
+</metadata>"#;
+    assert_eq!(
+        parse_latest_snapshot(metadata).as_deref(),
+        Some("1.3.0+56-2bd3d4cb-SNAPSHOT")
+    );
+    let without_latest = metadata.replace("<latest>1.3.0+56-2bd3d4cb-SNAPSHOT</latest>", "");
+    assert_eq!(
+        parse_latest_snapshot(&without_latest).as_deref(),
+        Some("1.3.0+56-2bd3d4cb-SNAPSHOT")
+    );
+    assert_eq!(parse_latest_snapshot("<metadata/>"), None);
+    assert!(is_snapshot("1.3.0+56-2bd3d4cb-SNAPSHOT"));
+    assert!(!is_snapshot("1.3.0"));
+}
@@ -0,0 +1,737 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::Path;
+
+// merge `overrides` into `base`, objects key by key and anything else by
+// replacing it. Nulls and empty strings don't count as a value, that's what
+// volt hands us for settings the user never touched
+pub fn deep_merge(base: &mut Value, overrides: &Value) {
+    match (base, overrides) {
+        _ if is_unset(overrides) => {}
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides.iter().filter(|(_, value)| !is_unset(value)) {
+                match base.get_mut(key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        let mut merged = Value::Null;
+                        deep_merge(&mut merged, value);
+                        base.insert(key.clone(), merged);
+                    }
+                }
+            }
+        }
+        // an object replacing anything else still leaves out its unset keys
+        (base, Value::Object(_)) => {
+            *base = Value::Object(Map::new());
+            deep_merge(base, overrides);
+        }
+        (base, overrides) => *base = overrides.clone(),
+    }
+}
+
+// like deep_merge for the user's settings changing while Metals runs,
+// except that a setting changed to null or an empty string was cleared and
+// its key goes. What Metals gets is rebuilt from them with BaseOptions
+pub fn apply_changes(base: &mut Value, changes: &Value) {
+    match (base, changes) {
+        (Value::Object(base), Value::Object(changes)) => {
+            for (key, value) in changes {
+                if is_unset(value) {
+                    base.remove(key);
+                    continue;
+                }
+                match base.get_mut(key) {
+                    Some(existing) if existing.is_object() && value.is_object() => {
+                        apply_changes(existing, value)
+                    }
+                    _ => {
+                        let mut changed = Value::Null;
+                        deep_merge(&mut changed, value);
+                        base.insert(key.clone(), changed);
+                    }
+                }
+            }
+        }
+        (base, changes) => deep_merge(base, changes),
+    }
+}
+
+fn is_unset(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        _ => false,
+    }
+}
+
+// what the plugin worked out itself
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Detected<'a> {
+    pub java_home: Option<&'a str>,
+    pub scala_version: Option<&'a str>,
+    // build server Metals imports without asking which one
+    pub default_bsp: Option<&'a str>,
+    pub custom_repositories: &'a [String],
+    // the Bloop installed, from PluginInfo
+    pub bloop_version: Option<&'a str>,
+}
+
+// the detected settings in the shape of Metals' own
+pub fn detected(found: Detected) -> Value {
+    let mut metals = Map::new();
+    if let Some(java_home) = found.java_home {
+        metals.insert("javaHome".to_string(), json!(java_home));
+    }
+    // used for Scala files outside of any build
+    if let Some(scala_version) = found.scala_version {
+        metals.insert("fallbackScalaVersion".to_string(), json!(scala_version));
+    }
+    if let Some(bsp) = found.default_bsp {
+        metals.insert("defaultBspToImport".to_string(), json!(bsp));
+    }
+    // so Metals connects to the Bloop server that's already there rather
+    // than starting its own version next to it
+    if let Some(bloop_version) = found.bloop_version {
+        metals.insert("bloopVersion".to_string(), json!(bloop_version));
+    }
+    // so Metals fetches its own dependencies from the same place
+    if !found.custom_repositories.is_empty() {
+        metals.insert(
+            "customRepositories".to_string(),
+            json!(found.custom_repositories),
+        );
+    }
+    // Metals then reports what it's busy with as metals/status, which we
+    // turn into progress items
+    json!({ "statusBarProvider": "on", "metals": metals })
+}
+
+// the initialization options Metals is started with, the user's own
+// settings winning over detected ones
+pub fn server_options(detected: Value, user: Option<&Value>) -> Option<Value> {
+    let mut options = detected;
+    if let Some(user) = user {
+        deep_merge(&mut options, user);
+    }
+    Some(options)
+}
+
+// what the options Metals gets are built from besides the user's settings,
+// kept with the server so a settings change rebuilds them the same way
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BaseOptions {
+    pub detected: Value,
+    // the repo's `.vscode/settings.json`, when importVscodeMetalsSettings is on
+    pub vscode: Option<Value>,
+}
+
+impl BaseOptions {
+    // what the plugin found out, then the inlay hints and excluded
+    // packages, then the repo's VS Code settings, the user's own options
+    // are merged on top so a metals.javaHome or metals.bloopVersion they
+    // set is never replaced, and one they clear falls back to the detected one
+    pub fn with_user(&self, user: Option<&Value>) -> Result<Option<Value>> {
+        let mut options = self.detected.clone();
+        deep_merge(
+            &mut options,
+            &InlayHintsConfig::from_options(user)?
+                .inlay_hints
+                .metals_options(),
+        );
+        deep_merge(
+            &mut options,
+            &CompletionConfig::from_options(user)?.metals_options()?,
+        );
+        if let Some(vscode) = &self.vscode {
+            deep_merge(&mut options, vscode);
+        }
+        Ok(server_options(options, user))
+    }
+}
+
+#[test]
+fn test_base_options_after_change() {
+    let base = BaseOptions {
+        detected: detected(Detected {
+            java_home: Some("jdk/temurin-21.0.2+13-linux-x64/jdk-21.0.2+13"),
+            bloop_version: Some("1.5.11"),
+            ..Detected::default()
+        }),
+        vscode: Some(json!({ "metals": { "showImplicitArguments": true } })),
+    };
+    // the whole config comes again, with the settings never touched unset
+    let mut user = json!({ "metals": { "javaHome": "/opt/graalvm-21" } });
+    apply_changes(
+        &mut user,
+        &json!({
+            "metals": { "javaHome": "", "bloopVersion": "" },
+            "inlayHints": { "inferredTypes": true },
+        }),
+    );
+    let options = base.with_user(Some(&user)).unwrap().unwrap();
+    assert_eq!(
+        options["metals"]["javaHome"],
+        "jdk/temurin-21.0.2+13-linux-x64/jdk-21.0.2+13"
+    );
+    assert_eq!(options["metals"]["bloopVersion"], "1.5.11");
+    assert_eq!(options["metals"]["showImplicitArguments"], true);
+    assert_eq!(
+        options["metals"]["inlayHints"]["inferredTypes"]["enable"],
+        true
+    );
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedSettingsConfig {
+    // the repo's `.vscode/settings.json`, for teams on both editors
+    #[serde(default)]
+    pub import_vscode_metals_settings: bool,
+}
+
+impl SharedSettingsConfig {
+    pub fn from_options(options: Option<&Value>) -> Result<SharedSettingsConfig> {
+        match options {
+            Some(options) => serde_json::from_value(options.clone())
+                .context("invalid importVscodeMetalsSettings"),
+            None => Ok(SharedSettingsConfig::default()),
+        }
+    }
+}
+
+// Metals' inlay hints, each kind switched on its own. Kinds left unset
+// keep Metals' default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHints {
+    #[serde(default, deserialize_with = "optional_bool")]
+    pub inferred_types: Option<bool>,
+    #[serde(default, deserialize_with = "optional_bool")]
+    pub implicit_arguments: Option<bool>,
+    #[serde(default, deserialize_with = "optional_bool")]
+    pub implicit_conversions: Option<bool>,
+    #[serde(default, deserialize_with = "optional_bool")]
+    pub type_parameters: Option<bool>,
+    #[serde(default, deserialize_with = "optional_bool")]
+    pub hints_in_pattern_match: Option<bool>,
+}
+
+// volt has no unset bool, these are strings defaulting to "" instead
+fn optional_bool<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<bool>, D::Error> {
+    match Value::deserialize(deserializer)? {
+        value if is_unset(&value) => Ok(None),
+        Value::Bool(enable) => Ok(Some(enable)),
+        Value::String(enable) => match enable.trim() {
+            "true" => Ok(Some(true)),
+            "false" => Ok(Some(false)),
+            other => Err(serde::de::Error::custom(format!(
+                "expected true, false or nothing, got {other:?}"
+            ))),
+        },
+        other => Err(serde::de::Error::custom(format!(
+            "expected true, false or nothing, got {other}"
+        ))),
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintsConfig {
+    #[serde(default)]
+    pub inlay_hints: InlayHints,
+}
+
+impl InlayHintsConfig {
+    pub fn from_options(options: Option<&Value>) -> Result<InlayHintsConfig> {
+        match options {
+            Some(options) => serde_json::from_value(options.clone()).context("invalid inlayHints"),
+            None => Ok(InlayHintsConfig::default()),
+        }
+    }
+}
+
+impl InlayHints {
+    // in the shape of Metals' settings: the `inlayHints` Metals 1.2 reads
+    // and the `show*` settings of the releases before it
+    pub fn metals_options(&self) -> Value {
+        let mut metals = Map::new();
+        let mut inlay_hints = Map::new();
+        let kinds = [
+            ("inferredTypes", self.inferred_types),
+            ("implicitArguments", self.implicit_arguments),
+            ("implicitConversions", self.implicit_conversions),
+            ("typeParameters", self.type_parameters),
+            ("hintsInPatternMatch", self.hints_in_pattern_match),
+        ];
+        for (kind, enable) in kinds {
+            if let Some(enable) = enable {
+                inlay_hints.insert(kind.to_string(), json!({ "enable": enable }));
+            }
+        }
+        if !inlay_hints.is_empty() {
+            metals.insert("inlayHints".to_string(), Value::Object(inlay_hints));
+        }
+        // a string, older Metals also took "minimal"
+        if let Some(enable) = self.inferred_types {
+            metals.insert("showInferredType".to_string(), json!(enable.to_string()));
+        }
+        if let Some(enable) = self.implicit_arguments {
+            metals.insert("showImplicitArguments".to_string(), json!(enable));
+        }
+        if let Some(enable) = self.implicit_conversions {
+            metals.insert(
+                "showImplicitConversionsAndClasses".to_string(),
+                json!(enable),
+            );
+        }
+        json!({ "metals": metals })
+    }
+}
+
+#[test]
+fn test_inlay_hints() {
+    let config = InlayHintsConfig::from_options(Some(&json!({
+        "inlayHints": {
+            "inferredTypes": false,
+            "implicitArguments": true,
+            "typeParameters": "false",
+            "implicitConversions": null,
+            "hintsInPatternMatch": "",
+        },
+    })))
+    .unwrap();
+    assert_eq!(
+        config.inlay_hints.metals_options(),
+        json!({
+            "metals": {
+                "inlayHints": {
+                    "inferredTypes": { "enable": false },
+                    "implicitArguments": { "enable": true },
+                    "typeParameters": { "enable": false },
+                },
+                "showInferredType": "false",
+                "showImplicitArguments": true,
+            }
+        })
+    );
+    let unset = InlayHintsConfig::default().inlay_hints.metals_options();
+    assert_eq!(unset, json!({ "metals": {} }));
+    assert!(InlayHintsConfig::from_options(Some(
+        &json!({ "inlayHints": { "inferredTypes": "minimal" } })
+    ))
+    .is_err());
+}
+
+// packages completions and auto-imports leave out. Each entry is a prefix,
+// `com.sun` covers `com.sun.net` too, and `--` in front puts one of Metals'
+// own exclusions back
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionConfig {
+    #[serde(default)]
+    pub excluded_packages: Vec<String>,
+}
+
+impl CompletionConfig {
+    pub fn from_options(options: Option<&Value>) -> Result<CompletionConfig> {
+        match options {
+            Some(options) => {
+                serde_json::from_value(options.clone()).context("invalid excludedPackages")
+            }
+            None => Ok(CompletionConfig::default()),
+        }
+    }
+
+    pub fn excluded_packages(&self) -> Result<Vec<String>> {
+        self.excluded_packages
+            .iter()
+            .map(|package| {
+                let package = package.trim();
+                let name = package.strip_prefix("--").unwrap_or(package);
+                if name.is_empty() || name.contains(char::is_whitespace) {
+                    Err(anyhow!(
+                        "excludedPackages entry {package:?} is not a package prefix like com.sun"
+                    ))
+                } else {
+                    Ok(package.to_string())
+                }
+            })
+            .collect()
+    }
+
+    // nothing when there's nothing excluded, Metals keeps its own list then
+    pub fn metals_options(&self) -> Result<Value> {
+        let excluded = self.excluded_packages()?;
+        if excluded.is_empty() {
+            return Ok(json!({ "metals": {} }));
+        }
+        Ok(json!({ "metals": { "excludedPackages": excluded } }))
+    }
+}
+
+#[test]
+fn test_excluded_packages() {
+    let config = CompletionConfig::from_options(Some(&json!({
+        "excludedPackages": [" com.sun ", "shaded.akka", "--sun"],
+    })))
+    .unwrap();
+    assert_eq!(
+        config.metals_options().unwrap(),
+        json!({ "metals": { "excludedPackages": ["com.sun", "shaded.akka", "--sun"] } })
+    );
+    assert_eq!(
+        CompletionConfig::default().metals_options().unwrap(),
+        json!({ "metals": {} })
+    );
+    for invalid in [
+        json!([""]),
+        json!(["  "]),
+        json!(["--"]),
+        json!(["com sun"]),
+    ] {
+        let config =
+            CompletionConfig::from_options(Some(&json!({ "excludedPackages": invalid }))).unwrap();
+        assert!(config.excluded_packages().is_err(), "{invalid}");
+    }
+    assert!(CompletionConfig::from_options(Some(&json!({ "excludedPackages": [1] }))).is_err());
+}
+
+// VS Code's settings.json allows comments and trailing commas, strip them
+// so it parses as plain JSON. Strings are copied as they are
+fn strip_jsonc(text: &str) -> String {
+    let mut json = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            json.push(c);
+            match c {
+                '\\' => json.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                json.push(c);
+            }
+            ('/', Some('/')) => while chars.next_if(|&next| next != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                let mut last = None;
+                for next in chars.by_ref() {
+                    if last == Some('*') && next == '/' {
+                        break;
+                    }
+                    last = Some(next);
+                }
+            }
+            ('}' | ']', _) => {
+                let end = json.trim_end().len();
+                if json[..end].ends_with(',') {
+                    json.truncate(end - 1);
+                }
+                json.push(c);
+            }
+            _ => json.push(c),
+        }
+    }
+    json
+}
+
+// the `metals.*` settings of the workspace's `.vscode/settings.json` in the
+// shape of ours, None without one. VS Code's dotted keys become nested
+// objects the way VS Code hands them to Metals, other keys are left out
+pub fn vscode_metals_settings(root: &Path) -> Result<Option<Value>> {
+    let path = root.join(".vscode").join("settings.json");
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("can't read {}", path.display())),
+    };
+    let settings: Map<String, Value> = serde_json::from_str(&strip_jsonc(&text))
+        .with_context(|| format!("{} isn't valid JSON", path.display()))?;
+    let mut metals = json!({});
+    for (key, value) in settings {
+        let nested = match key.strip_prefix("metals.") {
+            Some(path) => path
+                .rsplit('.')
+                .fold(value, |value, key| json!({ key: value })),
+            None if key == "metals" => value,
+            None => continue,
+        };
+        deep_merge(&mut metals, &nested);
+    }
+    Ok(Some(json!({ "metals": metals })))
+}
+
+#[test]
+fn test_vscode_metals_settings() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/vscode");
+    assert_eq!(
+        vscode_metals_settings(&fixture).unwrap(),
+        Some(json!({
+            "metals": {
+                "showImplicitArguments": true,
+                "scalafmtConfigPath": "config/.scalafmt.conf",
+                "inlayHints": { "typeParameters": { "enable": true } },
+                "excludedPackages": ["akka.actor.typed.javadsl", "https://not/a/comment"],
+            }
+        }))
+    );
+    assert_eq!(
+        vscode_metals_settings(&fixture.join(".vscode")).unwrap(),
+        None
+    );
+    assert_eq!(
+        strip_jsonc(r#"{"a": "\"//", /* b */ "c": [1, 2, ], }"#),
+        r#"{"a": "\"//",  "c": [1, 2]}"#
+    );
+
+    // beneath the user's own settings, above the detected ones
+    let mut options = detected(Detected {
+        scala_version: Some("3.3.1"),
+        ..Detected::default()
+    });
+    deep_merge(
+        &mut options,
+        &json!({ "metals": { "fallbackScalaVersion": "2.13.12", "showImplicitArguments": true } }),
+    );
+    let user = json!({ "metals": { "showImplicitArguments": false } });
+    let options = server_options(options, Some(&user)).unwrap();
+    assert_eq!(options["metals"]["fallbackScalaVersion"], "2.13.12");
+    assert_eq!(options["metals"]["showImplicitArguments"], false);
+}
+
+// Metals settings naming a config file, relative to the workspace root
+// unless they're absolute
+const CONFIG_FILE_SETTINGS: [&str; 2] = ["scalafmtConfigPath", "scalafixConfigPath"];
+
+// the config files the user pointed Metals at that aren't there, a typo
+// would otherwise only show as formatting or linting quietly doing nothing
+pub fn missing_config_files(user: Option<&Value>, root: &Path) -> Vec<String> {
+    CONFIG_FILE_SETTINGS
+        .iter()
+        .filter_map(|setting| {
+            let path = section(user, Some(&format!("metals.{setting}")));
+            let path = path
+                .as_str()
+                .map(str::trim)
+                .filter(|path| !path.is_empty())?;
+            (!root.join(path).is_file()).then(|| {
+                format!(
+                    "metals.{setting} {path} doesn't exist in {}",
+                    root.display()
+                )
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_missing_config_files() {
+    let root = std::env::temp_dir().join("scala-lapce-test-config-files");
+    std::fs::create_dir_all(root.join("config")).unwrap();
+    std::fs::write(root.join("config/scalafmt.conf"), "version = 3.7.17\n").unwrap();
+    let user = json!({
+        "metals": {
+            "scalafmtConfigPath": "config/scalafmt.conf",
+            "scalafixConfigPath": "config/scalafix.conf",
+        }
+    });
+    assert_eq!(
+        missing_config_files(Some(&user), &root),
+        [format!(
+            "metals.scalafixConfigPath config/scalafix.conf doesn't exist in {}",
+            root.display()
+        )]
+    );
+    let absolute = root.join("config/scalafmt.conf").display().to_string();
+    let user = json!({ "metals": { "scalafmtConfigPath": absolute, "scalafixConfigPath": "" } });
+    assert!(missing_config_files(Some(&user), &root).is_empty());
+    assert!(missing_config_files(None, &root).is_empty());
+}
+
+// the answer to one workspace/configuration item. `section` is a dotted
+// path like `metals` or `metals.javaHome`, without one Metals gets everything
+pub fn section(options: Option<&Value>, section: Option<&str>) -> Value {
+    let Some(options) = options else {
+        return Value::Null;
+    };
+    match section.filter(|section| !section.is_empty()) {
+        Some(section) => section
+            .split('.')
+            .try_fold(options, |value, key| value.get(key))
+            .cloned()
+            .unwrap_or(Value::Null),
+        None => options.clone(),
+    }
+}
+
+#[test]
+fn test_section() {
+    let options = json!({
+        "metals": {
+            "javaHome": "/usr/lib/jvm/temurin-21",
+            "showImplicitArguments": true,
+        },
+        "jdkVendor": "temurin",
+    });
+    assert_eq!(
+        section(Some(&options), Some("metals")),
+        json!({
+            "javaHome": "/usr/lib/jvm/temurin-21",
+            "showImplicitArguments": true,
+        })
+    );
+    assert_eq!(
+        section(Some(&options), Some("metals.javaHome")),
+        json!("/usr/lib/jvm/temurin-21")
+    );
+    assert_eq!(section(Some(&options), None), options);
+    assert_eq!(section(Some(&options), Some("files")), Value::Null);
+    assert_eq!(section(None, Some("metals")), Value::Null);
+}
+
+#[test]
+fn test_server_options_precedence() {
+    let detected = detected(Detected {
+        java_home: Some("/usr/lib/jvm/temurin-21"),
+        scala_version: Some("3.3.1"),
+        default_bsp: Some("mill-bsp"),
+        custom_repositories: &["https://artifactory.example.com/maven-remote".to_string()],
+        bloop_version: Some("1.5.11"),
+    });
+    let user = json!({
+        "metals": {
+            "javaHome": "/opt/graalvm-21",
+            "fallbackScalaVersion": "",
+            "bloopVersion": "1.5.6",
+            "showInferredType": true,
+        },
+        "jdkVendor": "graalvm",
+        "githubToken": null,
+    });
+    assert_eq!(
+        server_options(detected.clone(), Some(&user)),
+        Some(json!({
+            "metals": {
+                "javaHome": "/opt/graalvm-21",
+                "fallbackScalaVersion": "3.3.1",
+                "defaultBspToImport": "mill-bsp",
+                "customRepositories": ["https://artifactory.example.com/maven-remote"],
+                "bloopVersion": "1.5.6",
+                "showInferredType": true,
+            },
+            "jdkVendor": "graalvm",
+            "statusBarProvider": "on",
+        }))
+    );
+    assert_eq!(server_options(detected.clone(), None), Some(detected));
+}
+
+#[test]
+fn test_detected_json() {
+    let options = detected(Detected {
+        java_home: Some("/usr/lib/jvm/temurin-21"),
+        scala_version: Some("3.3.1"),
+        default_bsp: Some("sbt"),
+        bloop_version: Some("1.5.11"),
+        ..Detected::default()
+    });
+    assert_eq!(
+        serde_json::to_string(&options).unwrap(),
+        r#"{"metals":{"bloopVersion":"1.5.11","defaultBspToImport":"sbt","fallbackScalaVersion":"3.3.1","javaHome":"/usr/lib/jvm/temurin-21"},"statusBarProvider":"on"}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&detected(Detected::default())).unwrap(),
+        r#"{"metals":{},"statusBarProvider":"on"}"#
+    );
+}
+
+#[test]
+fn test_downloaded_java_home() {
+    let detected = detected(Detected {
+        java_home: Some("/home/jane/.local/share/lapce/plugins/scala/jdk/temurin-21.0.2+13-linux-x64/jdk-21.0.2+13"),
+        ..Detected::default()
+    });
+    // volt passes the unset setting as an empty string
+    let user = json!({ "metals": { "javaHome": "" } });
+    assert_eq!(
+        server_options(detected.clone(), Some(&user)).unwrap()["metals"]["javaHome"],
+        detected["metals"]["javaHome"]
+    );
+    let user = json!({ "metals": { "javaHome": "/usr/lib/jvm/java-17-openjdk" } });
+    assert_eq!(
+        server_options(detected, Some(&user)).unwrap()["metals"]["javaHome"],
+        "/usr/lib/jvm/java-17-openjdk"
+    );
+}
+
+#[test]
+fn test_deep_merge_replaces_non_objects() {
+    let mut base = json!({ "metals": { "serverProperties": ["-Xmx1g"] }, "lsp": "x" });
+    deep_merge(
+        &mut base,
+        &json!({ "metals": { "serverProperties": ["-Xmx4g"] }, "lsp": { "serverArgs": [] } }),
+    );
+    assert_eq!(
+        base,
+        json!({ "metals": { "serverProperties": ["-Xmx4g"] }, "lsp": { "serverArgs": [] } })
+    );
+}
+
+#[test]
+fn test_apply_changes() {
+    let mut base = json!({
+        "metals": { "javaHome": "/usr/lib/jvm/temurin-21", "showInferredType": false },
+        "jdkVendor": "temurin",
+    });
+    apply_changes(
+        &mut base,
+        &json!({
+            "metals": { "javaHome": "", "showInferredType": true, "bloopVersion": null },
+            "jdkVendor": null,
+            "lsp": { "serverArgs": ["-v"], "serverPath": "" },
+        }),
+    );
+    assert_eq!(
+        base,
+        json!({
+            "metals": { "showInferredType": true },
+            "lsp": { "serverArgs": ["-v"] },
+        })
+    );
+}
+
+#[test]
+fn test_deep_merge_skips_unset() {
+    let mut base = json!({ "metals": { "javaHome": "/usr/lib/jvm/temurin-21" } });
+    deep_merge(
+        &mut base,
+        &json!({
+            "metals": { "javaHome": "", "bloopVersion": null },
+            "githubToken": null,
+            "lsp": { "serverPath": "", "serverArgs": ["-v"] },
+        }),
+    );
+    assert_eq!(
+        base,
+        json!({
+            "metals": { "javaHome": "/usr/lib/jvm/temurin-21" },
+            "lsp": { "serverArgs": ["-v"] },
+        })
+    );
+    let mut base = Value::Null;
+    deep_merge(
+        &mut base,
+        &json!({ "jdkVersion": "17", "githubToken": null }),
+    );
+    assert_eq!(base, json!({ "jdkVersion": "17" }));
+}
@@ -0,0 +1,234 @@
+use lapce_plugin::psp_types::lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, WorkDoneProgress, WorkDoneProgressBegin,
+    WorkDoneProgressCreateParams, WorkDoneProgressEnd, WorkDoneProgressReport,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{logging, server};
+
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+// a `$/progress` notification shown by the host while some long running
+// work, like a download, is going on. Ends itself when dropped so an early
+// return on error doesn't leave it spinning forever
+pub struct Progress {
+    token: NumberOrString,
+    percentage: Option<u32>,
+    ended: bool,
+}
+
+impl Progress {
+    pub fn begin(title: &str) -> Progress {
+        let token = NumberOrString::String(format!(
+            "scala-lapce/{}",
+            NEXT_TOKEN.fetch_add(1, Ordering::Relaxed)
+        ));
+        // hosts that don't know the token just drop the notifications
+        if let Err(e) = server::host_request(
+            "window/workDoneProgress/create",
+            serde_json::to_value(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .unwrap_or_default(),
+        ) {
+            logging::debug(format!("no progress reporting: {e:#}"));
+        }
+        let progress = Progress {
+            token,
+            percentage: None,
+            ended: false,
+        };
+        progress.notify(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+            title: title.to_string(),
+            cancellable: Some(false),
+            message: None,
+            percentage: Some(0),
+        }));
+        progress
+    }
+
+    // `done` out of `total` bytes, only sent when the percentage moves so a
+    // download doesn't flood the host with notifications
+    pub fn report(&mut self, done: u64, total: Option<u64>) {
+        let percentage = percentage(done, total);
+        if percentage.is_none() || percentage == self.percentage {
+            return;
+        }
+        self.percentage = percentage;
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: total.map(|total| format!("{} / {}", megabytes(done), megabytes(total))),
+            percentage,
+        }));
+    }
+
+    // a new step of work without a percentage, like the file being indexed
+    pub fn message(&mut self, message: String, percentage: Option<u32>) {
+        self.percentage = percentage.or(self.percentage);
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: Some(message),
+            percentage: self.percentage,
+        }));
+    }
+
+    pub fn end(mut self, message: Option<String>) {
+        self.finish(message);
+    }
+
+    fn finish(&mut self, message: Option<String>) {
+        if !self.ended {
+            self.ended = true;
+            self.notify(WorkDoneProgress::End(WorkDoneProgressEnd { message }));
+        }
+    }
+
+    fn notify(&self, progress: WorkDoneProgress) {
+        let params = ProgressParams {
+            token: self.token.clone(),
+            value: ProgressParamsValue::WorkDone(progress),
+        };
+        if let Ok(params) = serde_json::to_value(params) {
+            server::host_notification("$/progress", params);
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish(None);
+    }
+}
+
+pub const METALS_STATUS: &str = "metals/status";
+
+// Metals' `metals/status`, what VS Code shows in its status bar. Only sent
+// when Metals was started with statusBarProvider on
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetalsStatusParams {
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub hide: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StatusUpdate {
+    Show(String),
+    Hide,
+}
+
+// the status text without the VS Code icons Metals puts in it, like
+// `$(sync~spin)`
+fn status_update(params: &MetalsStatusParams) -> StatusUpdate {
+    let text = params
+        .text
+        .split_whitespace()
+        .filter(|word| !(word.starts_with("$(") && word.ends_with(')')))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if params.hide == Some(true) || text.is_empty() {
+        StatusUpdate::Hide
+    } else {
+        StatusUpdate::Show(text)
+    }
+}
+
+#[test]
+fn test_status_update() {
+    let status = |text: &str, hide: Option<bool>| {
+        status_update(&MetalsStatusParams {
+            text: text.to_string(),
+            hide,
+        })
+    };
+    assert_eq!(
+        status("$(sync~spin) Importing build", None),
+        StatusUpdate::Show("Importing build".to_string())
+    );
+    assert_eq!(
+        status("Indexing 12/40", Some(false)),
+        StatusUpdate::Show("Indexing 12/40".to_string())
+    );
+    assert_eq!(status("$(check)", None), StatusUpdate::Hide);
+    assert_eq!(status("Indexing", Some(true)), StatusUpdate::Hide);
+}
+
+// what Metals is busy with, shown through the host's progress items so
+// importing and indexing a large build doesn't look like the editor hanging
+#[derive(Default)]
+pub struct ServerStatus {
+    status: Option<Progress>,
+    // Metals' own `$/progress`, by token
+    progress: HashMap<String, Progress>,
+}
+
+impl ServerStatus {
+    pub fn metals_status(&mut self, params: &MetalsStatusParams) {
+        match (status_update(params), self.status.as_mut()) {
+            (StatusUpdate::Show(text), Some(status)) => status.message(text, None),
+            (StatusUpdate::Show(text), None) => self.status = Some(Progress::begin(&text)),
+            (StatusUpdate::Hide, _) => {
+                if let Some(status) = self.status.take() {
+                    status.end(None);
+                }
+            }
+        }
+    }
+
+    pub fn progress(&mut self, params: ProgressParams) {
+        let token = match params.token {
+            NumberOrString::Number(token) => token.to_string(),
+            NumberOrString::String(token) => token,
+        };
+        // ours coming back, already shown
+        if token.starts_with("scala-lapce/") {
+            return;
+        }
+        let ProgressParamsValue::WorkDone(progress) = params.value;
+        match progress {
+            WorkDoneProgress::Begin(begin) => {
+                let mut progress = Progress::begin(&format!("Metals: {}", begin.title));
+                if let Some(message) = begin.message {
+                    progress.message(message, begin.percentage);
+                }
+                if let Some(previous) = self.progress.insert(token, progress) {
+                    previous.end(None);
+                }
+            }
+            WorkDoneProgress::Report(report) => {
+                if let Some(progress) = self.progress.get_mut(&token) {
+                    progress.message(report.message.unwrap_or_default(), report.percentage);
+                }
+            }
+            WorkDoneProgress::End(end) => {
+                if let Some(progress) = self.progress.remove(&token) {
+                    progress.end(end.message);
+                }
+            }
+        }
+    }
+}
+
+// None when the server didn't say how big the body is
+fn percentage(done: u64, total: Option<u64>) -> Option<u32> {
+    let total = total.filter(|total| *total > 0)?;
+    Some((done.min(total) * 100 / total) as u32)
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+}
+
+#[test]
+fn test_percentage() {
+    assert_eq!(percentage(0, Some(200)), Some(0));
+    assert_eq!(percentage(150, Some(200)), Some(75));
+    assert_eq!(percentage(300, Some(200)), Some(100));
+    assert_eq!(percentage(150, None), None);
+    assert_eq!(percentage(150, Some(0)), None);
+    assert_eq!(megabytes(195_400_000), "195.4 MB");
+}
@@ -0,0 +1,108 @@
+use lapce_plugin::{psp_types::ExecuteProcessResult, PLUGIN_RPC};
+#[cfg(test)]
+use std::collections::HashMap;
+use std::io;
+
+// what a finished child process left behind
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+// runs the toolchain binaries version detection asks about, so detection
+// can be tested without java, scala or sbt installed
+pub trait CommandRunner {
+    // an Err means the program couldn't be started at all, e.g. it isn't on PATH
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput>;
+}
+
+impl From<ExecuteProcessResult> for CommandOutput {
+    fn from(output: ExecuteProcessResult) -> Self {
+        let text = |bytes: Option<Vec<u8>>| {
+            String::from_utf8_lossy(&bytes.unwrap_or_default()).into_owned()
+        };
+        CommandOutput {
+            success: output.success,
+            stdout: text(output.stdout),
+            stderr: text(output.stderr),
+        }
+    }
+}
+
+fn command_line(program: &str, args: &[&str]) -> String {
+    [program]
+        .iter()
+        .chain(args)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// runs programs on the host. A WASI plugin can't spawn anything itself,
+// std::process only returns Unsupported there
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    // the host answers with an error when the program couldn't be started
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput> {
+        let output = PLUGIN_RPC
+            .execute_process(
+                program.to_string(),
+                args.iter().map(|arg| arg.to_string()).collect(),
+            )
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("couldn't run {program}: {e:?}"),
+                )
+            })?;
+        Ok(output.into())
+    }
+}
+
+// canned outputs keyed by the full command line, anything else is "not installed"
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockRunner {
+    outputs: HashMap<String, CommandOutput>,
+}
+
+#[cfg(test)]
+impl MockRunner {
+    pub fn with(mut self, command: &str, success: bool, stdout: &str, stderr: &str) -> Self {
+        self.outputs.insert(
+            command.to_string(),
+            CommandOutput {
+                success,
+                stdout: stdout.to_string(),
+                stderr: stderr.to_string(),
+            },
+        );
+        self
+    }
+}
+
+#[cfg(test)]
+impl CommandRunner for MockRunner {
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput> {
+        self.outputs
+            .get(&command_line(program, args))
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{program} not found")))
+    }
+}
+
+#[test]
+fn test_mock_runner() {
+    let runner = MockRunner::default().with("java --version", true, "openjdk 21.0.2", "");
+    assert_eq!(
+        runner.run("java", &["--version"]).unwrap().stdout,
+        "openjdk 21.0.2"
+    );
+    assert_eq!(
+        runner.run("java", &["-version"]).unwrap_err().kind(),
+        io::ErrorKind::NotFound
+    );
+}
@@ -0,0 +1,261 @@
+use regex::Regex::new as Regexp;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::runner::CommandRunner;
+#[cfg(test)]
+use crate::runner::MockRunner;
+
+// the sbt launcher installed on the system and the sbt the project builds with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SbtVersions {
+    pub system: Option<String>,
+    pub project: Option<String>,
+}
+
+// `sbt -version` prints something like
+// ```
+// sbt version in this project: 1.9.9
+// sbt script version: 1.9.8
+// ```
+// older launchers say "sbt launcher version" instead of "sbt script version"
+pub fn parse_sbt_version(output: &str) -> SbtVersions {
+    let re = Regexp(
+        r"sbt (?:(version in this project)|script version|launcher version):\s*(\d+\.\d+\.\d+\S*)",
+    )
+    .unwrap();
+    let mut versions = SbtVersions::default();
+    for captures in output.lines().filter_map(|line| re.captures(line)) {
+        let version = Some(captures[2].to_string());
+        if captures.get(1).is_some() {
+            versions.project = version;
+        } else {
+            versions.system = version;
+        }
+    }
+    versions
+}
+
+#[test]
+fn test_parse_sbt_version() {
+    let output = "\
+[info] welcome to sbt 1.9.9 (Eclipse Adoptium Java 21.0.2)
+[info] loading project definition from /home/jane/hello/project
+sbt version in this project: 1.9.9
+sbt script version: 1.9.8
+";
+    assert_eq!(
+        parse_sbt_version(output),
+        SbtVersions {
+            system: Some("1.9.8".to_string()),
+            project: Some("1.9.9".to_string()),
+        }
+    );
+
+    // outside of a project only the script version is known
+    let output = "\
+[warn] No sbt.version set in project/build.properties, base directory: /tmp
+sbt version in this project: 1.10.0-RC1
+sbt launcher version: 1.3.13
+";
+    assert_eq!(
+        parse_sbt_version(output),
+        SbtVersions {
+            system: Some("1.3.13".to_string()),
+            project: Some("1.10.0-RC1".to_string()),
+        }
+    );
+
+    // sbt on Windows, and the colored output it falls back to when it
+    // can't tell it isn't writing to a terminal
+    let output = "sbt version in this project: 1.9.9\r\nsbt script version: 1.9.9\r\n";
+    assert_eq!(
+        parse_sbt_version(output),
+        SbtVersions {
+            system: Some("1.9.9".to_string()),
+            project: Some("1.9.9".to_string()),
+        }
+    );
+    let output = "\u{1b}[0m[\u{1b}[0m\u{1b}[0minfo\u{1b}[0m] \u{1b}[0m\u{1b}[0mwelcome to sbt 1.9.9\u{1b}[0m\n\
+sbt version in this project: 1.9.9\n\
+sbt script version: 1.9.7\n";
+    assert_eq!(
+        parse_sbt_version(output),
+        SbtVersions {
+            system: Some("1.9.7".to_string()),
+            project: Some("1.9.9".to_string()),
+        }
+    );
+
+    assert_eq!(
+        parse_sbt_version("sbt script version: 1.9.8\n").project,
+        None
+    );
+    assert_eq!(parse_sbt_version(""), SbtVersions::default());
+}
+
+// sbt versions reported by `sbt -version`, which prints to stdout but
+// reports some launcher problems on stderr
+pub fn cli_versions(runner: &dyn CommandRunner) -> Option<SbtVersions> {
+    let output = runner.run("sbt", &["-version"]).ok()?;
+    Some(parse_sbt_version(&format!(
+        "{}\n{}",
+        output.stdout, output.stderr
+    )))
+}
+
+#[test]
+fn test_cli_versions() {
+    let runner = MockRunner::default().with(
+        "sbt -version",
+        true,
+        "sbt version in this project: 1.9.9\nsbt script version: 1.9.8\n",
+        "",
+    );
+    assert_eq!(
+        cli_versions(&runner),
+        Some(SbtVersions {
+            system: Some("1.9.8".to_string()),
+            project: Some("1.9.9".to_string()),
+        })
+    );
+    assert_eq!(cli_versions(&MockRunner::default()), None);
+}
+
+// `sbt.version` from the contents of a `project/build.properties` file
+pub fn parse_build_properties(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#') && !line.starts_with('!'))
+        .find_map(|line| {
+            let (key, value) = line.split_once(['=', ':'])?;
+            (key.trim() == "sbt.version").then(|| value.trim().to_string())
+        })
+        .filter(|version| !version.is_empty())
+}
+
+#[test]
+fn test_parse_build_properties() {
+    let contents = "\
+# generated by sbt new
+#sbt.version=1.5.0
+sbt.version = 1.9.9
+";
+    assert_eq!(parse_build_properties(contents).as_deref(), Some("1.9.9"));
+    assert_eq!(
+        parse_build_properties("sbt.version:1.10.0\n").as_deref(),
+        Some("1.10.0")
+    );
+    assert_eq!(parse_build_properties("sbt.version=\n"), None);
+    assert_eq!(parse_build_properties("scala.version=2.13.12\n"), None);
+}
+
+// the sbt version the project declares, without starting sbt
+pub fn project_version(root: &Path) -> Option<String> {
+    let contents = std::fs::read_to_string(root.join("project/build.properties")).ok()?;
+    parse_build_properties(&contents)
+}
+
+// JVM flags from `.jvmopts`, one or more per line. Only memory settings
+// and system properties (`-X`, `-XX` and `-D`) carry over to Metals, other
+// flags like `--add-opens` are for the code sbt runs
+pub fn parse_jvmopts(contents: &str) -> Vec<String> {
+    option_words(contents)
+        .filter(|flag| is_server_flag(flag))
+        .map(str::to_string)
+        .collect()
+}
+
+// JVM flags from `.sbtopts`, which mixes them (`-J-Xmx4G`, `-Dkey=value`)
+// with options only the sbt script understands, like `-mem 2048`
+pub fn parse_sbtopts(contents: &str) -> Vec<String> {
+    option_words(contents)
+        .filter_map(|word| match word.strip_prefix("-J") {
+            Some(flag) => Some(flag),
+            None => word.starts_with("-D").then_some(word),
+        })
+        .filter(|flag| is_server_flag(flag))
+        .map(str::to_string)
+        .collect()
+}
+
+fn option_words(contents: &str) -> impl Iterator<Item = &str> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(str::split_whitespace)
+}
+
+fn is_server_flag(flag: &str) -> bool {
+    (flag.starts_with("-X") || flag.starts_with("-D")) && flag.len() > 2
+}
+
+// the JVM settings the project gives sbt, `.sbtopts` after `.jvmopts` as
+// the sbt script reads them, so its flags win
+pub fn project_java_options(root: &Path) -> Vec<String> {
+    let read = |file: &str| std::fs::read_to_string(root.join(file)).unwrap_or_default();
+    let mut options = parse_jvmopts(&read(".jvmopts"));
+    options.extend(parse_sbtopts(&read(".sbtopts")));
+    options
+}
+
+#[test]
+fn test_project_java_options() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sbt-opts");
+    let jvmopts = std::fs::read_to_string(fixture.join(".jvmopts")).unwrap();
+    assert_eq!(
+        parse_jvmopts(&jvmopts),
+        ["-Xmx4G", "-Xss2m", "-XX:+UseG1GC", "-Dfile.encoding=UTF-8"]
+    );
+    let sbtopts = std::fs::read_to_string(fixture.join(".sbtopts")).unwrap();
+    assert_eq!(
+        parse_sbtopts(&sbtopts),
+        [
+            "-Xmx6G",
+            "-XX:ReservedCodeCacheSize=512m",
+            "-Dsbt.server.forcestart=true"
+        ]
+    );
+    assert_eq!(
+        project_java_options(&fixture),
+        [
+            "-Xmx4G",
+            "-Xss2m",
+            "-XX:+UseG1GC",
+            "-Dfile.encoding=UTF-8",
+            "-Xmx6G",
+            "-XX:ReservedCodeCacheSize=512m",
+            "-Dsbt.server.forcestart=true"
+        ]
+    );
+    assert!(project_java_options(&fixture.join("project")).is_empty());
+}
+
+// running `sbt -version` boots a JVM, so it's only done when the project
+// doesn't say which sbt it wants
+pub fn versions(root: Option<&Path>, runner: &dyn CommandRunner) -> SbtVersions {
+    match root.and_then(project_version) {
+        Some(project) => SbtVersions {
+            system: None,
+            project: Some(project),
+        },
+        None => cli_versions(runner).unwrap_or_default(),
+    }
+}
+
+#[test]
+fn test_versions_prefers_build_properties() {
+    let root = std::env::temp_dir().join("scala-lapce-test-sbt-versions");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("project")).unwrap();
+    std::fs::write(root.join("project/build.properties"), "sbt.version=1.9.9\n").unwrap();
+    assert_eq!(
+        versions(Some(&root), &MockRunner::default()),
+        SbtVersions {
+            system: None,
+            project: Some("1.9.9".to_string()),
+        }
+    );
+}
@@ -0,0 +1,366 @@
+use anyhow::{Context, Result};
+use regex::Regex::new as Regexp;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::logging;
+use crate::runner::CommandRunner;
+#[cfg(test)]
+use crate::runner::MockRunner;
+use crate::version::Version;
+
+// for scala we're actually only interested in the build tag,
+// primarily due to Scala 2 and Scala 3 differences. scala-cli also
+// prints its own version, which has to be skipped
+fn parse_scala_version(output: &str) -> Option<String> {
+    let re = Regexp(
+        r"Scala (?:code runner |compiler )?version(?: \(default\))?:? (\d+\.\d+\.\d+(?:-RC\d+)?)",
+    )
+    .unwrap();
+    re.captures(output).map(|captures| captures[1].to_string())
+}
+
+#[test]
+fn test_parse_scala_version() {
+    assert_eq!(
+        parse_scala_version("Scala code runner version 2.13.12 -- Copyright 2002-2023, LAMP/EPFL and Lightbend, Inc.")
+            .as_deref(),
+        Some("2.13.12")
+    );
+    assert_eq!(
+        parse_scala_version("Scala code runner version 3.3.1 -- Copyright 2002-2023, LAMP/EPFL")
+            .as_deref(),
+        Some("3.3.1")
+    );
+    assert_eq!(parse_scala_version("scala: command not found"), None);
+}
+
+// the version of the `scala` on PATH, None if there is none. Scala 2 prints
+// it to stderr, Scala 3 and scala-cli to stdout
+pub fn cli_version(runner: &dyn CommandRunner) -> Option<String> {
+    let output = runner.run("scala", &["-version"]).ok()?;
+    if !output.success {
+        return None;
+    }
+    parse_scala_version(&output.stderr).or_else(|| parse_scala_version(&output.stdout))
+}
+
+#[test]
+fn test_cli_version() {
+    let runner = MockRunner::default().with(
+        "scala -version",
+        true,
+        "",
+        "Scala code runner version 2.13.12 -- Copyright 2002-2023, LAMP/EPFL and Lightbend, Inc.\n",
+    );
+    assert_eq!(cli_version(&runner).as_deref(), Some("2.13.12"));
+
+    let runner = MockRunner::default().with(
+        "scala -version",
+        true,
+        "Scala CLI version: 1.1.2\nScala version (default): 3.3.1\n",
+        "",
+    );
+    assert_eq!(cli_version(&runner).as_deref(), Some("3.3.1"));
+
+    assert_eq!(cli_version(&MockRunner::default()), None);
+}
+
+// the .sbt files of the build definition in the workspace root, build.sbt first
+fn sbt_files(root: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(root)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "sbt"))
+                .filter(|path| path.is_file())
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort_by_key(|path| (!path.ends_with("build.sbt"), path.clone()));
+    files
+}
+
+// every Scala version a build definition declares through `scalaVersion`
+// or `crossScalaVersions`, in the order they appear, with `scalaVersion`
+// ones first. Versions kept in a `val` are followed, anything more
+// dynamic than that is skipped
+pub fn parse_build_versions(sources: &[String]) -> Vec<String> {
+    let sources: Vec<String> = sources
+        .iter()
+        .map(|source| {
+            source
+                .lines()
+                .filter(|line| !line.trim_start().starts_with("//"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect();
+
+    let vals = Regexp(r#"val\s+(\w+)\s*=\s*"([^"]+)""#).unwrap();
+    let vals: HashMap<String, String> = sources
+        .iter()
+        .flat_map(|source| vals.captures_iter(source))
+        .map(|captures| (captures[1].to_string(), captures[2].to_string()))
+        .collect();
+    let resolve = |value: &str| -> Option<String> {
+        let value = value.trim();
+        match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(literal) => Some(literal.to_string()),
+            None => vals.get(value).cloned(),
+        }
+    };
+
+    let scala_version = Regexp(r#"\bscalaVersion\s*:=\s*("[^"]+"|\w+)"#).unwrap();
+    let cross = Regexp(r#"\bcrossScalaVersions\s*:=\s*(?:Seq|List)\(([^)]*)\)"#).unwrap();
+    let mut versions = vec![];
+    for source in &sources {
+        versions.extend(
+            scala_version
+                .captures_iter(source)
+                .filter_map(|captures| resolve(&captures[1])),
+        );
+    }
+    for source in &sources {
+        for captures in cross.captures_iter(source) {
+            versions.extend(captures[1].split(',').filter_map(resolve));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    versions.retain(|version| seen.insert(version.clone()));
+    versions
+}
+
+pub fn build_versions(root: &Path) -> Vec<String> {
+    let sources: Vec<String> = sbt_files(root)
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .collect();
+    parse_build_versions(&sources)
+}
+
+// the Scala version the build targets, for telling when a project's Scala
+// is one Metals no longer serves
+pub fn build_version(root: &Path) -> Option<String> {
+    build_versions(root).into_iter().next()
+}
+
+#[test]
+fn test_build_version() {
+    let root = std::env::temp_dir().join("scala-lapce-test-scala-build-version");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    assert_eq!(build_version(&root), None);
+
+    std::fs::write(
+        root.join("build.sbt"),
+        "ThisBuild / scalaVersion := \"3.3.1\"\nname := \"hello\"\n",
+    )
+    .unwrap();
+    assert_eq!(build_version(&root).as_deref(), Some("3.3.1"));
+}
+
+#[test]
+fn test_build_versions_fixtures() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    assert_eq!(build_versions(&fixtures.join("sbt-single")), ["3.3.1"]);
+    assert_eq!(
+        build_versions(&fixtures.join("sbt-cross")),
+        ["2.13.12", "2.12.18", "3.3.1"]
+    );
+    assert_eq!(
+        build_version(&fixtures.join("sbt-cross")).as_deref(),
+        Some("2.13.12")
+    );
+}
+
+#[test]
+fn test_parse_build_versions() {
+    let sources = ["scalaVersion := scala3\ncrossScalaVersions := List(\"2.13.12\")".to_string()];
+    // an unresolvable val is skipped rather than taken literally
+    assert_eq!(parse_build_versions(&sources), ["2.13.12"]);
+    assert!(parse_build_versions(&["name := \"hello\"".to_string()]).is_empty());
+}
+
+// `.scala-version` holds just the version, asdf's `.tool-versions` has a
+// `<tool> <version> [fallback versions]` line per tool
+pub fn version_file_version(root: &Path) -> Option<String> {
+    if let Ok(contents) = std::fs::read_to_string(root.join(".scala-version")) {
+        let version = contents.trim();
+        if !version.is_empty() {
+            return Some(version.to_string());
+        }
+    }
+    let contents = std::fs::read_to_string(root.join(".tool-versions")).ok()?;
+    parse_tool_versions(&contents)
+}
+
+fn parse_tool_versions(contents: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let mut fields = line.split('#').next()?.split_whitespace();
+        match (fields.next(), fields.next()) {
+            (Some("scala"), Some(version)) => Some(version.to_string()),
+            _ => None,
+        }
+    })
+}
+
+#[test]
+fn test_version_file_version() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    assert_eq!(
+        version_file_version(&fixtures.join("asdf")).as_deref(),
+        Some("3.3.1")
+    );
+    assert_eq!(
+        parse_tool_versions("# scala 2.12.18\nsbt 1.9.9\nscala 2.13.12 system\n").as_deref(),
+        Some("2.13.12")
+    );
+    assert_eq!(parse_tool_versions("java 21\n"), None);
+
+    let root = std::env::temp_dir().join("scala-lapce-test-scala-version-file");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join(".scala-version"), "3.4.0\n").unwrap();
+    std::fs::write(root.join(".tool-versions"), "scala 2.13.12\n").unwrap();
+    assert_eq!(version_file_version(&root).as_deref(), Some("3.4.0"));
+}
+
+// places a Scala version can be detected from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum VersionSource {
+    // scalaVersion in the sbt build definition
+    Build,
+    // .scala-version or .tool-versions
+    VersionFile,
+    // the scala on PATH
+    Path,
+}
+
+// project files over whatever happens to be installed
+const DEFAULT_STRATEGY: [VersionSource; 3] = [
+    VersionSource::Build,
+    VersionSource::VersionFile,
+    VersionSource::Path,
+];
+
+// what Metals is resolved for when nothing says which Scala the project uses
+pub const DEFAULT_FALLBACK_SCALA: &str = "3.3.4";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionConfig {
+    #[serde(default = "default_strategy")]
+    pub version_detection_strategy: Vec<VersionSource>,
+    #[serde(default)]
+    pub fallback_scala_version: Option<String>,
+}
+
+fn default_strategy() -> Vec<VersionSource> {
+    DEFAULT_STRATEGY.to_vec()
+}
+
+impl Default for VersionConfig {
+    fn default() -> Self {
+        Self {
+            version_detection_strategy: default_strategy(),
+            fallback_scala_version: None,
+        }
+    }
+}
+
+impl VersionConfig {
+    pub fn from_options(options: Option<&Value>) -> Result<VersionConfig> {
+        match options {
+            Some(options) => serde_json::from_value(options.clone()).context(
+                "invalid versionDetectionStrategy, expected a list of build, versionFile and path",
+            ),
+            None => Ok(VersionConfig::default()),
+        }
+    }
+
+    // fallbackScalaVersion, or the default when it's unset or not a version
+    pub fn fallback(&self) -> String {
+        match self.fallback_scala_version.as_deref().map(str::trim) {
+            None | Some("") => DEFAULT_FALLBACK_SCALA.to_string(),
+            Some(version) if Version::parse(version).is_some() => version.to_string(),
+            Some(version) => {
+                logging::warn(format!(
+                    "fallbackScalaVersion {version:?} isn't a Scala version, using \
+                     {DEFAULT_FALLBACK_SCALA}"
+                ));
+                DEFAULT_FALLBACK_SCALA.to_string()
+            }
+        }
+    }
+}
+
+#[test]
+fn test_fallback_scala_version() {
+    assert_eq!(VersionConfig::default().fallback(), DEFAULT_FALLBACK_SCALA);
+    let config = |fallback: &str| {
+        VersionConfig::from_options(Some(&serde_json::json!({
+            "fallbackScalaVersion": fallback,
+        })))
+        .unwrap()
+    };
+    assert_eq!(config("2.13.14").fallback(), "2.13.14");
+    assert_eq!(config(" ").fallback(), DEFAULT_FALLBACK_SCALA);
+    assert_eq!(config("latest").fallback(), DEFAULT_FALLBACK_SCALA);
+}
+
+// the first source in the strategy that knows the Scala version
+pub fn detect(
+    root: Option<&Path>,
+    config: &VersionConfig,
+    runner: &dyn CommandRunner,
+) -> Option<String> {
+    config
+        .version_detection_strategy
+        .iter()
+        .find_map(|source| match source {
+            VersionSource::Build => root.and_then(build_version),
+            VersionSource::VersionFile => root.and_then(version_file_version),
+            VersionSource::Path => cli_version(runner),
+        })
+}
+
+#[test]
+fn test_detect() {
+    let root = std::env::temp_dir().join("scala-lapce-test-scala-detect");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("build.sbt"), "scalaVersion := \"2.13.12\"\n").unwrap();
+    std::fs::write(root.join(".scala-version"), "3.3.1\n").unwrap();
+
+    let config = VersionConfig::default();
+    assert_eq!(
+        detect(Some(&root), &config, &MockRunner::default()).as_deref(),
+        Some("2.13.12")
+    );
+
+    let config = VersionConfig::from_options(Some(&serde_json::json!({
+        "versionDetectionStrategy": ["versionFile", "build"],
+    })))
+    .unwrap();
+    assert_eq!(
+        detect(Some(&root), &config, &MockRunner::default()).as_deref(),
+        Some("3.3.1")
+    );
+
+    let config = VersionConfig::from_options(Some(&serde_json::json!({
+        "versionDetectionStrategy": ["build"],
+    })))
+    .unwrap();
+    assert_eq!(detect(None, &config, &MockRunner::default()), None);
+
+    assert!(VersionConfig::from_options(Some(&serde_json::json!({
+        "versionDetectionStrategy": ["sdkman"],
+    })))
+    .is_err());
+}
@@ -0,0 +1,183 @@
+use anyhow::{Context, Result};
+use lapce_plugin::psp_types::lsp_types::{DocumentFilter, DocumentSelector};
+use serde::Deserialize;
+use serde_json::Value;
+
+// what to do with `.sc` files, which are either worksheets or Ammonite /
+// scala-cli scripts. Metals tells them apart by the `.worksheet.sc` suffix
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScFiles {
+    // every `.sc` file goes to Metals, worksheets are evaluated, the rest
+    // handled as scripts
+    #[default]
+    Script,
+    // only `.worksheet.sc` files go to Metals, plain `.sc` files are left alone
+    Worksheet,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectorConfig {
+    #[serde(default)]
+    pub sc_files: ScFiles,
+    // off keeps worksheets away from Metals altogether, so opening one
+    // doesn't evaluate it
+    #[serde(default = "default_enable_worksheets")]
+    pub enable_worksheets: bool,
+}
+
+fn default_enable_worksheets() -> bool {
+    true
+}
+
+impl Default for SelectorConfig {
+    fn default() -> Self {
+        Self {
+            sc_files: ScFiles::default(),
+            enable_worksheets: default_enable_worksheets(),
+        }
+    }
+}
+
+impl SelectorConfig {
+    pub fn from_options(options: Option<&Value>) -> Result<SelectorConfig> {
+        match options {
+            Some(options) => serde_json::from_value(options.clone())
+                .context("invalid scFiles or enableWorksheets setting"),
+            None => Ok(SelectorConfig::default()),
+        }
+    }
+}
+
+fn scala_filter(pattern: &str) -> DocumentFilter {
+    DocumentFilter {
+        // lsp language id
+        language: Some(String::from("scala")),
+        // glob pattern
+        pattern: Some(String::from(pattern)),
+        // like file:
+        scheme: None,
+    }
+}
+
+// every `.sc` file but `.worksheet.sc` ones. Globs can't exclude, so
+// this is one pattern for each letter of `.worksheet` a script name can
+// differ in, plus the names that are a shorter part of it like `sheet.sc`
+fn script_filters() -> Vec<DocumentFilter> {
+    let suffix = ".worksheet";
+    let mut names = vec![];
+    for start in (0..=suffix.len()).rev() {
+        let (head, tail) = suffix.split_at(start);
+        if let Some(differing) = head.chars().last() {
+            names.push(format!("*[!{differing}]{tail}"));
+        }
+        if !tail.starts_with('.') && !tail.is_empty() {
+            names.push(tail.to_string());
+        }
+    }
+    names
+        .iter()
+        .map(|name| scala_filter(&format!("**/{name}.sc")))
+        .collect()
+}
+
+pub fn document_selector(config: &SelectorConfig) -> DocumentSelector {
+    match (config.sc_files, config.enable_worksheets) {
+        (ScFiles::Script, true) => vec![scala_filter("**/*.{scala,sbt,sc}")],
+        (ScFiles::Script, false) => {
+            let mut selector = vec![scala_filter("**/*.{scala,sbt}")];
+            selector.extend(script_filters());
+            selector
+        }
+        (ScFiles::Worksheet, true) => vec![
+            scala_filter("**/*.{scala,sbt}"),
+            scala_filter("**/*.worksheet.sc"),
+        ],
+        (ScFiles::Worksheet, false) => vec![scala_filter("**/*.{scala,sbt}")],
+    }
+}
+
+#[cfg(test)]
+fn matches(selector: &DocumentSelector, path: &str) -> bool {
+    selector.iter().any(|filter| {
+        globset::Glob::new(filter.pattern.as_deref().unwrap())
+            .unwrap()
+            .compile_matcher()
+            .is_match(path)
+    })
+}
+
+#[test]
+fn test_document_selector() {
+    let selector = document_selector(&SelectorConfig::default());
+    let pattern = selector[0].pattern.as_deref().unwrap();
+    assert_eq!(pattern, "**/*.{scala,sbt,sc}");
+    assert!(!pattern.contains(char::is_whitespace));
+}
+
+#[test]
+fn test_document_selector_matches_build_files() {
+    let selector = document_selector(&SelectorConfig::default());
+    assert!(matches(&selector, "build.sbt"));
+    assert!(matches(&selector, "project/plugins.sbt"));
+    assert!(matches(&selector, "src/main/scala/Main.scala"));
+    assert!(!matches(&selector, "project/build.properties"));
+}
+
+#[test]
+fn test_document_selector_sc_files() {
+    let fixture = "tests/fixtures/worksheet/hello.worksheet.sc";
+    assert!(std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join(fixture)
+        .exists());
+
+    let script = document_selector(&SelectorConfig::default());
+    assert!(matches(&script, fixture));
+    assert!(matches(&script, "scripts/release.sc"));
+
+    let config = SelectorConfig::from_options(Some(&serde_json::json!({
+        "scFiles": "worksheet",
+    })))
+    .unwrap();
+    let worksheet = document_selector(&config);
+    assert!(matches(&worksheet, fixture));
+    assert!(!matches(&worksheet, "scripts/release.sc"));
+    assert!(matches(&worksheet, "build.sbt"));
+}
+
+#[test]
+fn test_document_selector_without_worksheets() {
+    let fixture = "tests/fixtures/worksheet/hello.worksheet.sc";
+    let config = SelectorConfig {
+        enable_worksheets: false,
+        ..SelectorConfig::default()
+    };
+    let scripts = document_selector(&config);
+    assert!(!matches(&scripts, fixture));
+    assert!(!matches(&scripts, "notes/.worksheet.sc"));
+    for script in [
+        "worksheet.sc",
+        "scripts/release.sc",
+        "build.sc",
+        "sheet.sc",
+        "notes/spreadsheet.sc",
+        "a.sc",
+        "t.sc",
+        "myworksheet.sc",
+        "hello.worksheets.sc",
+        "hello.worksheet.scala.sc",
+    ] {
+        assert!(matches(&scripts, script), "{script}");
+    }
+    assert!(matches(&scripts, "src/main/scala/Main.scala"));
+
+    let config = SelectorConfig {
+        sc_files: ScFiles::Worksheet,
+        enable_worksheets: false,
+    };
+    let neither = document_selector(&config);
+    assert!(!matches(&neither, fixture));
+    assert!(!matches(&neither, "scripts/release.sc"));
+    assert!(matches(&neither, "build.sbt"));
+}
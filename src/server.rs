@@ -0,0 +1,275 @@
+use anyhow::{anyhow, Context, Result};
+use lapce_plugin::{
+    psp_types::lsp_types::{
+        ApplyWorkspaceEditParams, ApplyWorkspaceEditResponse, CodeAction, CodeActionContext,
+        CodeActionKind, CodeActionOrCommand, CodeActionParams, DocumentSelector,
+        ExecuteCommandParams, Range, ShowDocumentParams, TextDocumentIdentifier, Url,
+        WorkspaceEdit,
+    },
+    PLUGIN_RPC,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::options::BaseOptions;
+use crate::{logging, proxy};
+
+const INITIAL_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+// a language server we started, kept around so it can be restarted
+// without going through the JDK/Metals installation again
+#[derive(Debug, Clone)]
+pub struct Server {
+    pub server_uri: Url,
+    pub server_args: Vec<String>,
+    pub document_selector: DocumentSelector,
+    pub options: Option<Value>,
+    // what `options` was built from, None when they're the user's options
+    // as they are
+    pub base_options: Option<BaseOptions>,
+    // the downloads it runs from, relative to the plugin working directory,
+    // which cleaning up must leave alone
+    pub installs: Vec<PathBuf>,
+    // the Metals release it runs, None when it isn't one we resolved
+    pub metals_version: Option<String>,
+}
+
+impl Server {
+    pub fn start(&self) {
+        let args: Vec<String> = self
+            .server_args
+            .iter()
+            .map(|arg| proxy::redact(arg))
+            .collect();
+        logging::debug(format!("starting {} with {args:?}", self.server_uri));
+        PLUGIN_RPC.start_lsp(
+            self.server_uri.clone(),
+            self.server_args.clone(),
+            self.document_selector.clone(),
+            self.options.clone(),
+        );
+    }
+
+    // the regular LSP shutdown handshake, the server exits on `exit`.
+    // lapce-plugin has no call to stop what start_lsp started, both go out
+    // through the host like any other message to the server, and nothing
+    // in its API promises that Lapce hands them on. Only an answer to
+    // `shutdown` shows that Metals got it
+    pub fn stop(&self) -> Result<()> {
+        host_request("shutdown", Value::Null)
+            .context("Metals didn't answer the shutdown request")?;
+        host_notification("exit", Value::Null);
+        Ok(())
+    }
+
+    // started again only once Metals confirmed the shutdown, start_lsp
+    // next to a server that's still running would leave two of them. Not
+    // a server failure, so no restart gets scheduled for it either
+    pub fn restart(&self) -> Result<()> {
+        self.stop()
+            .context("Metals may still be running, reload the window to restart it")?;
+        self.start();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestartConfig {
+    // how often a crashed server is started again before giving up
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: u32,
+}
+
+fn default_max_restarts() -> u32 {
+    3
+}
+
+impl Default for RestartConfig {
+    fn default() -> Self {
+        RestartConfig {
+            max_restarts: default_max_restarts(),
+        }
+    }
+}
+
+impl RestartConfig {
+    pub fn from_options(options: Option<&Value>) -> Result<RestartConfig> {
+        match options {
+            Some(options) => serde_json::from_value(options.clone()).context("invalid maxRestarts"),
+            None => Ok(RestartConfig::default()),
+        }
+    }
+}
+
+// restarts of a server that died on us, each one waiting twice as long as
+// the one before
+#[derive(Debug, Clone, Default)]
+pub struct Restarts {
+    pub attempts: u32,
+}
+
+impl Restarts {
+    // how long to wait before the next restart, None once `max` are used up
+    pub fn next_backoff(&mut self, max: u32) -> Option<Duration> {
+        if self.attempts >= max {
+            return None;
+        }
+        self.attempts += 1;
+        Some(INITIAL_RESTART_BACKOFF * 2u32.pow(self.attempts - 1))
+    }
+
+    // the server answered again, a later crash starts over
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+#[test]
+fn test_restarts() {
+    let mut restarts = Restarts::default();
+    assert_eq!(restarts.next_backoff(3), Some(Duration::from_secs(1)));
+    assert_eq!(restarts.next_backoff(3), Some(Duration::from_secs(2)));
+    assert_eq!(restarts.next_backoff(3), Some(Duration::from_secs(4)));
+    assert_eq!(restarts.next_backoff(3), None);
+    restarts.reset();
+    assert_eq!(restarts.next_backoff(3), Some(Duration::from_secs(1)));
+    assert_eq!(Restarts::default().next_backoff(0), None);
+
+    assert_eq!(RestartConfig::default().max_restarts, 3);
+    let config =
+        RestartConfig::from_options(Some(&serde_json::json!({ "maxRestarts": 5 }))).unwrap();
+    assert_eq!(config.max_restarts, 5);
+    assert!(RestartConfig::from_options(Some(&serde_json::json!({ "maxRestarts": -1 }))).is_err());
+}
+
+// anything the host doesn't handle itself is forwarded to the server the
+// plugin started
+pub fn host_request(method: &str, params: Value) -> Result<Value> {
+    PLUGIN_RPC
+        .host_request(method, params)
+        .map_err(|e| anyhow!("{method} failed: {e:?}"))
+}
+
+// a request meant for the server that came back without an answer, the
+// only sign we get of a server that crashed
+#[derive(Debug)]
+pub struct RequestFailed {
+    method: String,
+    error: String,
+}
+
+impl fmt::Display for RequestFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed: {}", self.method, self.error)
+    }
+}
+
+impl std::error::Error for RequestFailed {}
+
+// host_request for the server's own methods, so a failure can be told
+// apart from a command that had nothing to do
+pub fn server_request(method: &str, params: Value) -> Result<Value> {
+    PLUGIN_RPC.host_request(method, params).map_err(|e| {
+        RequestFailed {
+            method: method.to_string(),
+            error: format!("{e:?}"),
+        }
+        .into()
+    })
+}
+
+// whether `error` came from the server not answering, rather than from
+// what the user asked for
+pub fn is_server_failure(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<RequestFailed>().is_some()
+}
+
+#[test]
+fn test_is_server_failure() {
+    let failed = anyhow::Error::new(RequestFailed {
+        method: "workspace/executeCommand".to_string(),
+        error: "connection closed".to_string(),
+    });
+    assert_eq!(
+        failed.to_string(),
+        "workspace/executeCommand failed: connection closed"
+    );
+    assert!(is_server_failure(&failed.context("Metals doctor")));
+    assert!(!is_server_failure(&anyhow!("no stack trace to analyze")));
+}
+
+pub fn host_notification(method: &str, params: Value) {
+    PLUGIN_RPC.host_notification(method, params);
+}
+
+// reply to a request the server sent us through the host
+pub fn respond(id: u64, result: Value) {
+    PLUGIN_RPC.host_success(id, result);
+}
+
+// ask the editor to open a file
+pub fn show_document(uri: Url) -> Result<()> {
+    host_request(
+        "window/showDocument",
+        serde_json::to_value(ShowDocumentParams {
+            uri,
+            external: Some(false),
+            take_focus: Some(true),
+            selection: None,
+        })?,
+    )?;
+    Ok(())
+}
+
+// the server's source actions of one kind for a whole file
+pub fn source_actions(uri: Url, kind: CodeActionKind) -> Result<Vec<CodeActionOrCommand>> {
+    let params = CodeActionParams {
+        text_document: TextDocumentIdentifier { uri },
+        range: Range::default(),
+        context: CodeActionContext {
+            diagnostics: vec![],
+            only: Some(vec![kind]),
+            trigger_kind: None,
+        },
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    };
+    let actions = server_request("textDocument/codeAction", serde_json::to_value(params)?)?;
+    // null when there's nothing to do
+    Ok(serde_json::from_value::<Option<_>>(actions)?.unwrap_or_default())
+}
+
+// the server may leave out the edit until the action is picked
+pub fn resolve_action(action: CodeAction) -> Result<CodeAction> {
+    let action = server_request("codeAction/resolve", serde_json::to_value(action)?)?;
+    Ok(serde_json::from_value(action)?)
+}
+
+pub fn apply_edit(label: Option<String>, edit: WorkspaceEdit) -> Result<()> {
+    let response = host_request(
+        "workspace/applyEdit",
+        serde_json::to_value(ApplyWorkspaceEditParams { label, edit })?,
+    )?;
+    let response: ApplyWorkspaceEditResponse = serde_json::from_value(response)?;
+    if !response.applied {
+        return Err(anyhow!(
+            "the edit wasn't applied: {}",
+            response.failure_reason.unwrap_or_default()
+        ));
+    }
+    Ok(())
+}
+
+// run one of the server's own `workspace/executeCommand` commands
+pub fn execute_command(command: &str, arguments: Vec<Value>) -> Result<Value> {
+    let params = ExecuteCommandParams {
+        command: command.to_string(),
+        arguments,
+        work_done_progress_params: Default::default(),
+    };
+    server_request("workspace/executeCommand", serde_json::to_value(params)?)
+}
@@ -0,0 +1,137 @@
+use regex::Regex::new as Regexp;
+use std::cmp::Ordering;
+use std::fmt;
+
+// a `major.minor.patch` version as Metals, Scala and the JDKs number their
+// releases, with an optional `-pre` release and `+build` suffix, e.g.
+// `1.0.0-RC1`, `21.0.2+13` or `1.3.0+12-5a8d0b2e-SNAPSHOT`. Left out minor
+// and patch numbers count as 0
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub pre_release: Option<String>,
+    pub build: Option<String>,
+}
+
+impl Version {
+    pub fn parse(version: &str) -> Option<Version> {
+        let re =
+            Regexp(r"^(\d+)(?:\.(\d+))?(?:\.(\d+))?(?:-([0-9A-Za-z.-]+))?(?:\+([0-9A-Za-z.-]+))?$")
+                .ok()?;
+        let captures = re.captures(version.trim())?;
+        let number = |i: usize| {
+            captures
+                .get(i)
+                .map_or(Some(0), |part| part.as_str().parse().ok())
+        };
+        let text = |i: usize| captures.get(i).map(|part| part.as_str().to_string());
+        Some(Version {
+            major: number(1)?,
+            minor: number(2)?,
+            patch: number(3)?,
+            pre_release: text(4),
+            build: text(5),
+        })
+    }
+
+    // the build as a number, like the 13 of `21.0.2+13`
+    pub fn build_number(&self) -> Option<u64> {
+        self.build.as_deref()?.parse().ok()
+    }
+}
+
+// a pre-release comes before its release, builds after it, numbered
+// builds in numeric order
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let pre_release = match (&self.pre_release, &other.pre_release) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(a), Some(b)) => a.cmp(b),
+        };
+        let build = match (self.build_number(), other.build_number()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => self.build.cmp(&other.build),
+        };
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then(pre_release)
+            .then(build)
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre_release) = &self.pre_release {
+            write!(f, "-{pre_release}")?;
+        }
+        if let Some(build) = &self.build {
+            write!(f, "+{build}")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_parse_version() {
+    let version = Version::parse("21.0.2+13").unwrap();
+    assert_eq!((version.major, version.minor, version.patch), (21, 0, 2));
+    assert_eq!(version.build_number(), Some(13));
+    let version = Version::parse("22-ea+27").unwrap();
+    assert_eq!(version.major, 22);
+    assert_eq!(version.pre_release.as_deref(), Some("ea"));
+    let snapshot = Version::parse("1.3.0+12-5a8d0b2e-SNAPSHOT").unwrap();
+    assert_eq!(snapshot.build.as_deref(), Some("12-5a8d0b2e-SNAPSHOT"));
+    assert_eq!(snapshot.build_number(), None);
+    assert_eq!(Version::parse(" 3.3.1\n"), Version::parse("3.3.1"));
+
+    for garbage in ["", "v1.3.0", "1.3.0.4", "1..3", "latest", "1.3.0+", "-1"] {
+        assert_eq!(Version::parse(garbage), None, "{garbage:?}");
+    }
+}
+
+#[test]
+fn test_version_order() {
+    // every version of a small grid against every other one, ordered like
+    // the numbers they're made of
+    let mut versions = vec![];
+    for major in 0..4 {
+        for minor in [0, 1, 9, 10] {
+            for patch in [0, 2, 11] {
+                versions.push((major, minor, patch));
+            }
+        }
+    }
+    for a in &versions {
+        let parsed_a = Version::parse(&format!("{}.{}.{}", a.0, a.1, a.2)).unwrap();
+        assert_eq!(
+            Version::parse(&parsed_a.to_string()),
+            Some(parsed_a.clone())
+        );
+        for b in &versions {
+            let parsed_b = Version::parse(&format!("{}.{}.{}", b.0, b.1, b.2)).unwrap();
+            assert_eq!(parsed_a.cmp(&parsed_b), a.cmp(b), "{a:?} {b:?}");
+        }
+        let rc = Version::parse(&format!("{}.{}.{}-RC1", a.0, a.1, a.2)).unwrap();
+        let build = Version::parse(&format!("{}.{}.{}+2", a.0, a.1, a.2)).unwrap();
+        assert!(rc < parsed_a);
+        assert!(parsed_a < build);
+        assert_eq!(Version::parse(&rc.to_string()), Some(rc));
+    }
+
+    let version = |version: &str| Version::parse(version).unwrap();
+    assert!(version("21.0.2+13") > version("21.0.2+9"));
+    assert!(version("21") == version("21.0.0"));
+    assert!(version("1.10.0") > version("1.9.9"));
+    assert!(version("1.0.0-RC2") > version("1.0.0-RC1"));
+}
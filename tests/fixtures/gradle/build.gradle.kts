@@ -0,0 +1,12 @@
+plugins {
+    scala
+    application
+}
+
+repositories {
+    mavenCentral()
+}
+
+dependencies {
+    implementation("org.scala-lang:scala3-library_3:3.3.1")
+}
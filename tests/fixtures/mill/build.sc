@@ -0,0 +1,5 @@
+import mill._, scalalib._
+
+object foo extends ScalaModule {
+  def scalaVersion = "3.3.1"
+}
@@ -0,0 +1 @@
+@main def hello(): Unit = println("Hello from Mill")
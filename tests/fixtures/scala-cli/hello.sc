@@ -0,0 +1,2 @@
+#!/usr/bin/env -S scala-cli shebang
+println(os.pwd)
@@ -0,0 +1,4 @@
+val greeting = "Hello"
+val names = List("Metals", "Lapce")
+
+names.map(name => s"$greeting, $name!")